@@ -65,6 +65,11 @@ impl Task for SnakeTask {
             false => Some(self.score),
         }
     }
+
+    fn partial_score(&self) -> Option<f32> {
+        // Games cut off by the `max_steps` safety cap keep their score so far
+        Some(self.score)
+    }
 }
 
 const NEAT_FILE: &str = "examples/snake.data";
@@ -79,12 +84,18 @@ fn main() {
     let mut neat = match neat_from_file() {
         Ok(neat) => neat,
         _ => {
-            let mut settings = NeatSettings::default();
-            settings.weight_mutate = 3.7;
-            settings.species_threshold = 1.45;
-            settings.add_connection_rate = 0.44;
-            settings.reset_fitness = false;
-            settings.connections_diff = 1.0;
+            let settings = NeatSettings {
+                weight_mutate: 3.7,
+                // Matches the species 1.45 gave before distances were divided by genome size
+                species_threshold: 1.1,
+                add_connection_rate: 0.44,
+                reset_fitness: false,
+                connections_diff: 1.0,
+                // Safety cap on top of the 100 steps without food a game is allowed, long
+                // enough for a snake to fill the board
+                max_steps: Some(20_000),
+                ..NeatSettings::default()
+            };
 
             // Create Neat using Task implemented above
             Neat::<SnakeTask>::new(3000, 24, 1, settings)
@@ -0,0 +1,113 @@
+use serde::{Deserialize, Serialize};
+
+/// Function applied by a node to `activation * aggregated + bias`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Activation {
+    /// `1 / (1 + exp(-x))`, used by every node created by `Neat`
+    #[default]
+    Sigmoid,
+    Tanh,
+    Sin,
+    /// `exp(-x^2)`
+    Gauss,
+    Relu,
+    Identity,
+    /// `x` clamped to `[-1, 1]`
+    Clamped,
+    Abs,
+    Square,
+}
+
+impl Activation {
+    pub(crate) fn apply(self, x: f32) -> f32 {
+        match self {
+            Activation::Sigmoid => 1.0 / (1.0 + (-x).exp()),
+            Activation::Tanh => x.tanh(),
+            Activation::Sin => x.sin(),
+            Activation::Gauss => (-x.powi(2)).exp(),
+            Activation::Relu => x.max(0.0),
+            Activation::Identity => x,
+            Activation::Clamped => x.clamp(-1.0, 1.0),
+            Activation::Abs => x.abs(),
+            Activation::Square => x.powi(2),
+        }
+    }
+
+    /// Rust expression performing the same operations as `apply` on the expression `x`
+    pub(crate) fn rust_source(self, x: &str) -> String {
+        match self {
+            Activation::Sigmoid => format!("1.0 / (1.0 + (-({})).exp())", x),
+            Activation::Tanh => format!("({}).tanh()", x),
+            Activation::Sin => format!("({}).sin()", x),
+            Activation::Gauss => format!("(-({}).powi(2)).exp()", x),
+            Activation::Relu => format!("({}).max(0.0)", x),
+            Activation::Identity => x.to_string(),
+            Activation::Clamped => format!("({}).clamp(-1.0, 1.0)", x),
+            Activation::Abs => format!("({}).abs()", x),
+            Activation::Square => format!("({}).powi(2)", x),
+        }
+    }
+}
+
+/// How a node combines its weighted inputs, nodes without inputs always aggregate to 0.0
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Aggregation {
+    #[default]
+    Sum,
+    Product,
+    Max,
+    Min,
+    Mean,
+    /// Input with the largest absolute value
+    MaxAbs,
+}
+
+impl Aggregation {
+    pub(crate) fn start(self) -> f32 {
+        match self {
+            Aggregation::Sum | Aggregation::Mean | Aggregation::MaxAbs => 0.0,
+            Aggregation::Product => 1.0,
+            Aggregation::Max => f32::NEG_INFINITY,
+            Aggregation::Min => f32::INFINITY,
+        }
+    }
+
+    pub(crate) fn fold(self, acc: f32, x: f32) -> f32 {
+        match self {
+            Aggregation::Sum | Aggregation::Mean => acc + x,
+            Aggregation::Product => acc * x,
+            Aggregation::Max => acc.max(x),
+            Aggregation::Min => acc.min(x),
+            Aggregation::MaxAbs => {
+                if x.abs() > acc.abs() {
+                    x
+                } else {
+                    acc
+                }
+            }
+        }
+    }
+
+    pub(crate) fn finish(self, acc: f32, count: usize) -> f32 {
+        match self {
+            _ if count == 0 => 0.0,
+            Aggregation::Mean => acc / count as f32,
+            _ => acc,
+        }
+    }
+
+    /// Rust statement performing the same operation as `fold` on the variable `acc`
+    pub(crate) fn rust_source(self, acc: &str, x: &str) -> String {
+        match self {
+            Aggregation::Sum | Aggregation::Mean => format!("{} += {};", acc, x),
+            Aggregation::Product => format!("{} *= {};", acc, x),
+            Aggregation::Max => format!("{} = {}.max({});", acc, acc, x),
+            Aggregation::Min => format!("{} = {}.min({});", acc, acc, x),
+            Aggregation::MaxAbs => {
+                format!("if ({}).abs() > {}.abs() {{ {} = {}; }}", x, acc, acc, x)
+            }
+        }
+    }
+}
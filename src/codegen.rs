@@ -0,0 +1,403 @@
+use indexmap::IndexMap;
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use super::activation::{Activation, Aggregation};
+
+/// Node of a `Network` with its activation, bias, functions and incoming connections as
+/// `(start, weight)`
+pub(crate) struct Source {
+    pub(crate) activation: f32,
+    pub(crate) bias: f32,
+    pub(crate) function: Activation,
+    pub(crate) aggregation: Aggregation,
+    pub(crate) inputs: Vec<(u16, f32)>,
+}
+
+enum Op {
+    /// Start aggregating the inputs of a node into an accumulator
+    Begin(usize, u16),
+    /// Aggregate value of a node multiplied by a weight into an accumulator
+    Add(usize, u16, f32),
+    /// Apply activation to an accumulator and store it as the value of a node
+    Assign(u16, usize),
+}
+
+/// Records the operations `Network` `prop` performs, in the same order, so generated code
+/// produces identical results
+struct Trace<'a> {
+    nodes: &'a IndexMap<u16, Source>,
+    solved: HashSet<u16>,
+    assigned: HashSet<u16>,
+    stateful: bool,
+    accumulators: usize,
+    ops: Vec<Op>,
+}
+
+impl Trace<'_> {
+    fn read(&mut self, node: u16) {
+        if !self.assigned.contains(&node) {
+            self.stateful = true;
+        }
+    }
+
+    fn eval(&mut self, node: u16) -> usize {
+        let acc = self.accumulators;
+        self.accumulators += 1;
+        self.ops.push(Op::Begin(acc, node));
+
+        let nodes = self.nodes;
+        for (start, weight) in nodes[&node].inputs.iter() {
+            if !self.solved.contains(start) {
+                self.solved.insert(*start);
+                let child = self.eval(*start);
+                self.ops.push(Op::Assign(*start, child));
+                self.assigned.insert(*start);
+            }
+
+            self.read(*start);
+            self.ops.push(Op::Add(acc, *start, *weight));
+        }
+
+        acc
+    }
+}
+
+fn literal(x: f32) -> String {
+    if x.is_nan() {
+        String::from("f32::NAN")
+    } else if x.is_infinite() {
+        String::from(if x > 0.0 {
+            "f32::INFINITY"
+        } else {
+            "f32::NEG_INFINITY"
+        })
+    } else if x.is_sign_negative() {
+        format!("({:?}_f32)", x)
+    } else {
+        format!("{:?}_f32", x)
+    }
+}
+
+fn camel_case(name: &str) -> String {
+    name.split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            let first = chars.next().unwrap().to_ascii_uppercase();
+            first.to_string() + chars.as_str()
+        })
+        .collect()
+}
+
+/// Generates Rust source reproducing `Network` `prop`. Feedforward networks become a function
+/// from inputs to outputs, networks whose outputs depend on previous values become a struct
+pub(crate) fn rust_source(
+    name: &str,
+    inputs: u16,
+    outputs: u16,
+    nodes: &IndexMap<u16, Source>,
+) -> String {
+    let mut trace = Trace {
+        nodes,
+        solved: (0..inputs).collect(),
+        assigned: (0..inputs).collect(),
+        stateful: false,
+        accumulators: 0,
+        ops: vec![],
+    };
+
+    for i in inputs..inputs + outputs {
+        let acc = trace.eval(i);
+        trace.ops.push(Op::Assign(i, acc));
+        trace.assigned.insert(i);
+    }
+
+    let stateful = trace.stateful;
+    let index = |id: &u16| nodes.get_index_of(id).unwrap();
+    let value = |id: &u16| {
+        if stateful {
+            format!("self.values[{}]", index(id))
+        } else {
+            format!("n{}", id)
+        }
+    };
+
+    // Accumulators without any connections stay at zero
+    let mut used = vec![false; trace.accumulators];
+    let mut owner = vec![0; trace.accumulators];
+    for op in trace.ops.iter() {
+        match op {
+            Op::Begin(acc, node) => owner[*acc] = *node,
+            Op::Add(acc, _, _) => used[*acc] = true,
+            Op::Assign(_, _) => {}
+        }
+    }
+
+    // A function recomputes an output read by a later output, binding it again. The earlier
+    // binding, the nodes only it reads and inputs no connection reads would be unused variables
+    let mut live = vec![true; trace.ops.len()];
+    if !stateful {
+        let mut needed: HashSet<u16> = (inputs..inputs + outputs).collect();
+        let mut live_acc = vec![false; trace.accumulators];
+        for (i, op) in trace.ops.iter().enumerate().rev() {
+            live[i] = match op {
+                Op::Assign(node, acc) => {
+                    live_acc[*acc] = needed.remove(node);
+                    live_acc[*acc]
+                }
+                Op::Add(acc, start, _) => {
+                    if live_acc[*acc] {
+                        needed.insert(*start);
+                    }
+                    live_acc[*acc]
+                }
+                Op::Begin(acc, _) => live_acc[*acc],
+            };
+        }
+    }
+    let read: HashSet<u16> = trace
+        .ops
+        .iter()
+        .zip(live.iter())
+        .filter_map(|(op, live)| match op {
+            Op::Add(_, start, _) if *live && *start < inputs => Some(*start),
+            _ => None,
+        })
+        .collect();
+
+    let indent = if stateful { "        " } else { "    " };
+    let mut body = String::new();
+    for i in 0..inputs {
+        let line = if stateful {
+            format!("self.values[{}] = inputs[{}];", index(&i), i)
+        } else if read.contains(&i) {
+            format!("let n{} = inputs[{}];", i, i)
+        } else {
+            continue;
+        };
+        writeln!(body, "{}{}", indent, line).unwrap();
+    }
+
+    for (op, _) in trace.ops.iter().zip(live).filter(|(_, live)| *live) {
+        let line = match op {
+            Op::Begin(acc, node) if used[*acc] => format!(
+                "let mut s{} = {};",
+                acc,
+                literal(nodes[node].aggregation.start())
+            ),
+            Op::Begin(_, _) => continue,
+            Op::Add(acc, start, weight) => nodes[&owner[*acc]].aggregation.rust_source(
+                &format!("s{}", acc),
+                &format!("{} * {}", value(start), literal(*weight)),
+            ),
+            Op::Assign(node, acc) => {
+                let source = &nodes[node];
+                let aggregated = match source.aggregation {
+                    _ if !used[*acc] => String::from("0.0_f32"),
+                    Aggregation::Mean => {
+                        format!("(s{} / {})", acc, literal(source.inputs.len() as f32))
+                    }
+                    _ => format!("s{}", acc),
+                };
+                let expr = source.function.rust_source(&format!(
+                    "{} * {} + {}",
+                    literal(source.activation),
+                    aggregated,
+                    literal(source.bias)
+                ));
+
+                if stateful {
+                    format!("self.values[{}] = {};", index(node), expr)
+                } else {
+                    format!("let n{} = {};", node, expr)
+                }
+            }
+        };
+        writeln!(body, "{}{}", indent, line).unwrap();
+    }
+
+    let results: Vec<String> = (inputs..inputs + outputs).map(|i| value(&i)).collect();
+    writeln!(body, "{}[{}]", indent, results.join(", ")).unwrap();
+
+    let mut source = String::new();
+    if stateful {
+        let name = camel_case(name);
+        writeln!(
+            source,
+            "/// Recurrent network generated by `neat`, `prop` propagates inputs and returns \
+             outputs\npub struct {name} {{\n    values: [f32; {len}],\n}}\n\n\
+             impl {name} {{\n    pub fn new() -> {name} {{\n        {name} {{\n            \
+             values: [0.0; {len}],\n        }}\n    }}\n\n    \
+             pub fn reset(&mut self) {{\n        self.values = [0.0; {len}];\n    }}\n\n    \
+             pub fn prop(&mut self, inputs: [f32; {inputs}]) -> [f32; {outputs}] {{",
+            name = name,
+            len = nodes.len(),
+            inputs = inputs,
+            outputs = outputs
+        )
+        .unwrap();
+        source.push_str(&body);
+        source.push_str("    }\n}\n");
+        writeln!(
+            source,
+            "\nimpl Default for {name} {{\n    fn default() -> {name} {{\n        \
+             {name}::new()\n    }}\n}}",
+            name = name
+        )
+        .unwrap();
+    } else {
+        let parameter = if read.is_empty() { "_inputs" } else { "inputs" };
+        writeln!(
+            source,
+            "/// Feedforward network generated by `neat`\n\
+             pub fn {}({}: [f32; {}]) -> [f32; {}] {{",
+            name, parameter, inputs, outputs
+        )
+        .unwrap();
+        source.push_str(&body);
+        source.push_str("}\n");
+    }
+
+    source
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::activation::{Activation, Aggregation};
+    use crate::fixtures;
+    use crate::genome::{Genome, GenomeBuilder};
+    use crate::neat::NeatSettings;
+    use crate::network::Network;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::fmt::Write;
+    use std::fs;
+    use std::process::Command;
+
+    const FUNCTIONS: [Activation; 9] = [
+        Activation::Sigmoid,
+        Activation::Tanh,
+        Activation::Sin,
+        Activation::Gauss,
+        Activation::Relu,
+        Activation::Identity,
+        Activation::Clamped,
+        Activation::Abs,
+        Activation::Square,
+    ];
+
+    const AGGREGATIONS: [Aggregation; 6] = [
+        Aggregation::Sum,
+        Aggregation::Product,
+        Aggregation::Max,
+        Aggregation::Min,
+        Aggregation::Mean,
+        Aggregation::MaxAbs,
+    ];
+
+    fn genomes(feedforward: bool, seed: u64) -> Vec<Genome> {
+        let settings = NeatSettings {
+            feedforward,
+            add_connection_rate: 0.9,
+            add_node_rate: 0.5,
+            ..NeatSettings::default()
+        };
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        fixtures::genomes(3, 2, 6, 20, &settings, seed)
+            .into_iter()
+            .map(|mut genome| {
+                for (_, node) in genome.nodes.iter_mut() {
+                    node.bias = rng.gen_range(-1.0, 1.0);
+                    node.function = FUNCTIONS[rng.gen_range(0, FUNCTIONS.len())];
+                    node.aggregation = AGGREGATIONS[rng.gen_range(0, AGGREGATIONS.len())];
+                }
+                genome
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_rust_source() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let inputs: Vec<[f32; 3]> = (0..5)
+            .map(|_| {
+                [
+                    rng.gen_range(-1.0, 1.0),
+                    rng.gen(),
+                    rng.gen_range(-5.0, 5.0),
+                ]
+            })
+            .collect();
+
+        let mut program = String::new();
+        let mut main = String::from("fn main() {\n");
+        let mut expected = String::new();
+        let mut recurrent = 0;
+
+        // Networks reading only some inputs or none
+        let partial = GenomeBuilder::new(3, 2).connection(1, 3, 0.5).build();
+        let unconnected = Genome::new(3, 2);
+        let all = genomes(true, 2)
+            .into_iter()
+            .chain(genomes(false, 3))
+            .chain(vec![partial.unwrap(), unconnected]);
+        for (i, genome) in all.enumerate() {
+            let mut network = Network::new(genome).unwrap();
+            let source = network.to_rust_source("net");
+            let stateful = source.contains("pub struct");
+
+            writeln!(program, "mod m{} {{\n{}}}", i, source).unwrap();
+            if stateful {
+                recurrent += 1;
+                writeln!(main, "    let mut n{} = m{}::Net::default();", i, i).unwrap();
+                writeln!(main, "    n{}.reset();", i).unwrap();
+            }
+
+            network.reset();
+            for input in inputs.iter() {
+                network.prop(input.to_vec());
+                for output in network.get_outputs() {
+                    writeln!(expected, "{:08x}", output.to_bits()).unwrap();
+                }
+
+                let call = if stateful {
+                    format!("n{}.prop({:?})", i, input)
+                } else {
+                    format!("m{}::net({:?})", i, input)
+                };
+                writeln!(
+                    main,
+                    "    for o in {}.iter() {{ println!(\"{{:08x}}\", o.to_bits()); }}",
+                    call
+                )
+                .unwrap();
+            }
+        }
+        assert!(recurrent > 0 && recurrent < 12);
+        main.push_str("}\n");
+        program.push_str(&main);
+
+        let dir = std::env::temp_dir().join(format!("neat-codegen-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("main.rs");
+        let binary = dir.join("main");
+        fs::write(&file, program).unwrap();
+
+        let rustc = std::env::var("RUSTC").unwrap_or_else(|_| String::from("rustc"));
+        let status = Command::new(rustc)
+            .arg(&file)
+            .args(["-D", "warnings"])
+            .arg("-o")
+            .arg(&binary)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let output = Command::new(&binary).output().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+    }
+}
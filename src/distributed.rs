@@ -0,0 +1,464 @@
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use super::evaluator::{Evaluation, Evaluator};
+use super::neat::{evaluate, NeatSettings};
+use super::network::{Network, Task};
+
+#[derive(Serialize, Deserialize)]
+enum Request {
+    Generation {
+        seeds: Vec<u64>,
+        settings: Box<NeatSettings>,
+    },
+    Job {
+        id: usize,
+        network: Network,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+struct Response {
+    id: usize,
+    evaluation: Evaluation,
+}
+
+/// `Evaluator` that sends networks to worker processes over TCP and collects their fitness.
+/// Workers connect with `run_worker` and can join or leave at any point, jobs of a worker that
+/// disconnects or doesn't reply within the timeout are given to the remaining workers. A job
+/// that has failed more than the retry limit is given `NeatSettings` `panic_fitness` instead,
+/// so a network that crashes or hangs every worker can't stall evaluation. While no workers
+/// are connected evaluation waits for one. The port is released when the coordinator is dropped
+pub struct Coordinator {
+    addr: SocketAddr,
+    incoming: Receiver<TcpStream>,
+    workers: Vec<TcpStream>,
+    timeout: Duration,
+    retries: usize,
+    stop: Arc<AtomicBool>,
+    accept: Option<JoinHandle<()>>,
+}
+
+impl Coordinator {
+    /// Listen for workers on the given address
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - Address to accept worker connections on
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Coordinator> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        listener.set_nonblocking(true)?;
+        let (sender, incoming) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let stopped = Arc::clone(&stop);
+        let accept = thread::spawn(move || {
+            while !stopped.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        if stream.set_nonblocking(false).is_err() {
+                            continue;
+                        }
+                        if sender.send(stream).is_err() {
+                            break;
+                        }
+                    }
+                    Err(_) => thread::sleep(Duration::from_millis(20)),
+                }
+            }
+        });
+
+        Ok(Coordinator {
+            addr,
+            incoming,
+            workers: vec![],
+            timeout: Duration::from_secs(60),
+            retries: 2,
+            stop,
+            accept: Some(accept),
+        })
+    }
+
+    /// Sets how long to wait for a worker to finish an episode before giving the job to another
+    /// worker and disconnecting it, 60 seconds by default. A worker has this times the number
+    /// of episodes to reply to a job
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Longest time one episode of a network should take
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout.max(Duration::from_millis(1));
+    }
+
+    /// Sets how many times a job is given to another worker after a worker disconnects or times
+    /// out on it, 2 by default. Once these are used up the network is given `NeatSettings`
+    /// `panic_fitness` and a `Failure`
+    ///
+    /// # Arguments
+    ///
+    /// * `retries` - Times a failed job is tried again
+    pub fn set_retries(&mut self, retries: usize) {
+        self.retries = retries;
+    }
+
+    /// Address workers should connect to
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Number of workers currently connected
+    pub fn workers(&mut self) -> usize {
+        self.workers.extend(self.incoming.try_iter());
+        self.workers.len()
+    }
+
+    fn work(mut stream: &TcpStream, timeout: Duration, generation: &Generation) -> bool {
+        let Generation {
+            request,
+            jobs,
+            queue,
+            results,
+            ..
+        } = generation;
+
+        // A worker that times out is treated as disconnected
+        let timeouts = stream
+            .set_read_timeout(Some(timeout))
+            .and_then(|_| stream.set_write_timeout(Some(timeout)));
+        if timeouts.is_err() || stream.write_all(request).is_err() {
+            return false;
+        }
+
+        let mut reader = BufReader::new(stream);
+
+        loop {
+            let id = match queue.lock().unwrap().pop() {
+                Some(id) => id,
+                None => return true,
+            };
+
+            let response = stream
+                .write_all(&jobs[id])
+                .map_err(bincode::Error::from)
+                .and_then(|_| bincode::deserialize_from::<_, Response>(&mut reader));
+
+            match response {
+                Ok(response) if response.id == id => {
+                    results.lock().unwrap()[id] = Some(response.evaluation);
+                }
+                _ => {
+                    generation.fail(id);
+                    return false;
+                }
+            }
+        }
+    }
+}
+
+/// Jobs of one call to `evaluations` shared between the threads talking to workers
+struct Generation<'a> {
+    request: Vec<u8>,
+    jobs: Vec<Vec<u8>>,
+    queue: Mutex<Vec<usize>>,
+    results: Mutex<Vec<Option<Evaluation>>>,
+    attempts: Mutex<Vec<usize>>,
+    retries: usize,
+    seeds: &'a [u64],
+    settings: &'a NeatSettings,
+}
+
+impl Generation<'_> {
+    /// Queues a job again after a worker failed on it, or gives it `panic_fitness` once it is out
+    /// of retries
+    fn fail(&self, id: usize) {
+        let mut attempts = self.attempts.lock().unwrap();
+        attempts[id] += 1;
+
+        if attempts[id] > self.retries {
+            let message = format!("Job failed on {} workers", attempts[id]);
+            let seed = self.seeds.first().copied().unwrap_or(0);
+            self.results.lock().unwrap()[id] =
+                Some(Evaluation::failed(self.settings, seed, message));
+        } else {
+            self.queue.lock().unwrap().push(id);
+        }
+    }
+}
+
+impl Evaluator for Coordinator {
+    fn evaluate(
+        &mut self,
+        networks: Vec<Network>,
+        seeds: &[u64],
+        settings: &NeatSettings,
+    ) -> Vec<f32> {
+        self.evaluations(networks, seeds, settings)
+            .into_iter()
+            .map(|evaluation| evaluation.fitness)
+            .collect()
+    }
+
+    fn evaluations(
+        &mut self,
+        networks: Vec<Network>,
+        seeds: &[u64],
+        settings: &NeatSettings,
+    ) -> Vec<Evaluation> {
+        let request = bincode::serialize(&Request::Generation {
+            seeds: seeds.to_vec(),
+            settings: Box::new(settings.clone()),
+        })
+        .unwrap();
+
+        let jobs: Vec<Vec<u8>> = networks
+            .into_iter()
+            .enumerate()
+            .map(|(id, network)| bincode::serialize(&Request::Job { id, network }).unwrap())
+            .collect();
+
+        let generation = Generation {
+            request,
+            queue: Mutex::new((0..jobs.len()).rev().collect()),
+            results: Mutex::new(vec![None; jobs.len()]),
+            attempts: Mutex::new(vec![0; jobs.len()]),
+            jobs,
+            retries: self.retries,
+            seeds,
+            settings,
+        };
+
+        while !generation.queue.lock().unwrap().is_empty() {
+            if self.workers() == 0 {
+                match self.incoming.recv() {
+                    Ok(stream) => self.workers.push(stream),
+                    Err(_) => panic!("Coordinator stopped accepting workers"),
+                }
+            }
+
+            let timeout = self.timeout * seeds.len().max(1) as u32;
+            let generation = &generation;
+            let alive: Vec<bool> = thread::scope(|scope| {
+                let handles: Vec<_> = self
+                    .workers
+                    .iter()
+                    .map(|stream| {
+                        scope.spawn(move || Coordinator::work(stream, timeout, generation))
+                    })
+                    .collect();
+
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().unwrap_or(false))
+                    .collect()
+            });
+
+            let mut alive = alive.into_iter();
+            self.workers.retain(|_| alive.next().unwrap());
+        }
+
+        generation
+            .results
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .map(|evaluation| evaluation.expect("Every job should be evaluated"))
+            .collect()
+    }
+}
+
+impl Drop for Coordinator {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(accept) = self.accept.take() {
+            let _ = accept.join();
+        }
+    }
+}
+
+/// Connect to a `Coordinator` and evaluate the networks it sends on `Task` until the coordinator
+/// disconnects. Run one worker per core to use every core of a machine
+///
+/// # Arguments
+///
+/// * `addr` - Address of the `Coordinator`
+///
+/// # Example
+///
+/// ```ignore
+/// neat::run_worker::<TaskImplementation, _>("127.0.0.1:7878").unwrap();
+/// ```
+pub fn run_worker<T: Task, A: ToSocketAddrs>(addr: A) -> io::Result<()> {
+    let mut stream = TcpStream::connect(addr)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut generation = (vec![], None);
+
+    loop {
+        let request = match bincode::deserialize_from(&mut reader) {
+            Ok(request) => request,
+            Err(err) => {
+                return match *err {
+                    bincode::ErrorKind::Io(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                        Ok(())
+                    }
+                    bincode::ErrorKind::Io(err) => Err(err),
+                    err => Err(io::Error::new(io::ErrorKind::InvalidData, err)),
+                };
+            }
+        };
+
+        match request {
+            Request::Generation { seeds, settings } => generation = (seeds, Some(settings)),
+            Request::Job { id, network } => {
+                let (seeds, settings) = &generation;
+                let settings = settings.as_ref().ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, "Job sent before generation")
+                })?;
+
+                let evaluation = evaluate::<T>(network, seeds, settings);
+                let response = bincode::serialize(&Response { id, evaluation }).unwrap();
+                stream.write_all(&response)?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    struct Sum {
+        seed: u64,
+        steps: u64,
+        score: f32,
+    }
+
+    impl Task for Sum {
+        fn new(seed: u64) -> Sum {
+            Sum {
+                seed,
+                steps: 0,
+                score: 0.0,
+            }
+        }
+
+        fn step(&mut self, inputs: Vec<f32>) -> Vec<f32> {
+            self.score += inputs[0];
+            self.steps += 1;
+            vec![(self.seed % 7) as f32, self.steps as f32]
+        }
+
+        fn score(&self) -> Option<f32> {
+            if self.steps < 5 {
+                None
+            } else {
+                Some(self.score)
+            }
+        }
+
+        fn behavior(&self) -> Option<Vec<f32>> {
+            Some(vec![self.score, self.seed as f32])
+        }
+    }
+
+    fn networks() -> Vec<Network> {
+        fixtures::genomes(2, 1, 20, 1, &NeatSettings::default(), 3)
+            .into_iter()
+            .map(|genome| Network::new(genome).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_coordinator() {
+        let settings = NeatSettings::default();
+        let seeds = [3, 12, 20];
+        let expected: Vec<Evaluation> = networks()
+            .into_iter()
+            .map(|network| evaluate::<Sum>(network, &seeds, &settings))
+            .collect();
+        let fitness: Vec<f32> = expected.iter().map(|e| e.fitness).collect();
+
+        let mut coordinator = Coordinator::bind("127.0.0.1:0").unwrap();
+        let addr = coordinator.local_addr();
+
+        coordinator.set_timeout(Duration::from_millis(200));
+
+        // Workers that drop their connection or stop replying after receiving a job
+        let faulty: Vec<_> = (0..2)
+            .map(|hang| {
+                thread::spawn(move || {
+                    let stream = TcpStream::connect(addr).unwrap();
+                    let mut reader = BufReader::new(stream);
+                    for _ in 0..2 + hang {
+                        if bincode::deserialize_from::<_, Request>(&mut reader).is_err() {
+                            break;
+                        }
+                    }
+                })
+            })
+            .collect();
+        while coordinator.workers() < 2 {
+            thread::yield_now();
+        }
+
+        let workers: Vec<_> = (0..2)
+            .map(|_| thread::spawn(move || run_worker::<Sum, _>(addr).unwrap()))
+            .collect();
+
+        assert_eq!(coordinator.evaluate(networks(), &seeds, &settings), fitness);
+        assert_eq!(
+            coordinator.evaluations(networks(), &seeds, &settings),
+            expected
+        );
+
+        drop(coordinator);
+        for worker in faulty.into_iter().chain(workers) {
+            worker.join().unwrap();
+        }
+        // The port is released once the coordinator is dropped
+        TcpListener::bind(addr).unwrap();
+    }
+
+    #[test]
+    fn test_retries() {
+        let settings = NeatSettings::default();
+        let mut coordinator = Coordinator::bind("127.0.0.1:0").unwrap();
+        let addr = coordinator.local_addr();
+        coordinator.set_timeout(Duration::from_millis(50));
+        coordinator.set_retries(1);
+
+        // Workers that never reply, as if the network made every worker hang
+        let hanging: Vec<_> = (0..2)
+            .map(|_| {
+                thread::spawn(move || {
+                    let stream = TcpStream::connect(addr).unwrap();
+                    let mut reader = BufReader::new(stream);
+                    while bincode::deserialize_from::<_, Request>(&mut reader).is_ok() {}
+                })
+            })
+            .collect();
+        while coordinator.workers() < 2 {
+            thread::yield_now();
+        }
+
+        let network = networks().remove(0);
+        let evaluations = coordinator.evaluations(vec![network], &[7, 8], &settings);
+        assert_eq!(evaluations[0].fitness, settings.panic_fitness);
+        let failure = evaluations[0].failure.as_ref().unwrap();
+        assert_eq!(failure.seed, 7);
+        assert!(failure.message.contains("2 workers"));
+        assert_eq!(coordinator.workers(), 0);
+
+        drop(coordinator);
+        for worker in hanging {
+            worker.join().unwrap();
+        }
+    }
+}
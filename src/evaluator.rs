@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+
+use super::neat::NeatSettings;
+use super::network::Network;
+
+/// Result of running a network for every episode of a generation
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Evaluation {
+    /// Scores of the episodes combined by `NeatSettings` `aggregate`
+    pub fitness: f32,
+    /// Behaviours from `Task` `behavior` of every episode joined in order, empty when the task
+    /// doesn't describe its behaviour
+    pub behavior: Vec<f32>,
+    /// Each objective from `Task` `objectives` combined over the episodes by `NeatSettings`
+    /// `aggregate`, empty when the task has no objectives
+    pub objectives: Vec<f32>,
+    /// Episode that panicked or failed, ending the evaluation early
+    pub failure: Option<Failure>,
+}
+
+/// Episode of a network whose `Task` panicked or whose `Evaluator` failed to run it
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Failure {
+    /// Seed of the episode, or the first seed of the generation when a `Coordinator` gave up on
+    /// the whole job
+    pub seed: u64,
+    /// Panic message or error
+    pub message: String,
+}
+
+impl Evaluation {
+    /// Evaluation of a genome whose `Task` panicked or failed
+    pub(crate) fn failed(settings: &NeatSettings, seed: u64, message: String) -> Evaluation {
+        Evaluation {
+            fitness: settings.panic_fitness,
+            failure: Some(Failure { seed, message }),
+            ..Evaluation::default()
+        }
+    }
+
+    /// Combines the evaluations of single episodes
+    pub(crate) fn combine(episodes: Vec<Evaluation>, settings: &NeatSettings) -> Evaluation {
+        let length = episodes.iter().map(|e| e.objectives.len()).min();
+        let objectives = (0..length.unwrap_or(0))
+            .map(|i| {
+                let values = episodes.iter().map(|e| e.objectives[i]).collect();
+                settings.aggregate.apply(values)
+            })
+            .collect();
+
+        Evaluation {
+            fitness: settings
+                .aggregate
+                .apply(episodes.iter().map(|e| e.fitness).collect()),
+            behavior: episodes.into_iter().flat_map(|e| e.behavior).collect(),
+            objectives,
+            failure: None,
+        }
+    }
+}
+
+/// Finds the fitness of networks in place of `Neat` running `Task` locally, such as on other
+/// machines or in other processes
+pub trait Evaluator: Send {
+    /// Returns the fitness of each network, in the same order as `networks`
+    ///
+    /// # Arguments
+    ///
+    /// * `networks` - Networks to evaluate
+    /// * `seeds` - Seed for each episode, shared by every network
+    /// * `settings` - Settings containing aggregation and limits for each episode
+    fn evaluate(
+        &mut self,
+        networks: Vec<Network>,
+        seeds: &[u64],
+        settings: &NeatSettings,
+    ) -> Vec<f32>;
+
+    /// Same as `evaluate` but also returns the behaviour and objectives of each network, used for
+    /// novelty search and multi-objective selection. Defaults to `evaluate` with neither
+    ///
+    /// # Arguments
+    ///
+    /// * `networks` - Networks to evaluate
+    /// * `seeds` - Seed for each episode, shared by every network
+    /// * `settings` - Settings containing aggregation and limits for each episode
+    fn evaluations(
+        &mut self,
+        networks: Vec<Network>,
+        seeds: &[u64],
+        settings: &NeatSettings,
+    ) -> Vec<Evaluation> {
+        self.evaluate(networks, seeds, settings)
+            .into_iter()
+            .map(|fitness| Evaluation {
+                fitness,
+                ..Evaluation::default()
+            })
+            .collect()
+    }
+}
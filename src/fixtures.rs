@@ -0,0 +1,40 @@
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use super::genome::Genome;
+use super::innovation::InnovationCounter;
+use super::neat::NeatSettings;
+
+/// Genomes sharing innovation numbers, each given one connection attempt and then mutated, the
+/// same for every call with the same arguments
+///
+/// # Arguments
+///
+/// * `inputs` - Number of inputs of each genome
+/// * `outputs` - Number of outputs of each genome
+/// * `count` - Number of genomes
+/// * `mutations` - Times each genome is mutated with `settings`
+/// * `settings` - Mutation rates and whether genomes are feedforward
+/// * `seed` - Seed of the mutations
+pub(crate) fn genomes(
+    inputs: u16,
+    outputs: u16,
+    count: usize,
+    mutations: usize,
+    settings: &NeatSettings,
+    seed: u64,
+) -> Vec<Genome> {
+    let mut innovations = InnovationCounter::new(inputs + outputs);
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    (0..count)
+        .map(|_| {
+            let mut genome = Genome::new(inputs, outputs);
+            genome.add_connection(&mut innovations, settings, &mut rng);
+            for _ in 0..mutations {
+                genome.mutate(&mut innovations, settings, &mut rng);
+            }
+            genome
+        })
+        .collect()
+}
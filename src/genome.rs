@@ -1,24 +1,183 @@
 use indexmap::IndexMap;
+use rand::seq::SliceRandom;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 use std::cmp::max;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
 
+use super::activation::{Activation, Aggregation};
 use super::innovation::InnovationCounter;
-use super::neat::NeatSettings;
+use super::json::Document;
+use super::neat::{InitialConnection, NeatSettings};
+use super::neat_python;
+use super::visualize::{Graph, Link};
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
-pub(crate) struct Neuron {
+/// Node of a `Genome`, its value is `function(activation * aggregation(inputs) + bias)` where
+/// `inputs` are the values of nodes connected into it multiplied by the connection weights
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Neuron {
     pub(crate) activation: f32,
+    pub(crate) bias: f32,
+    pub(crate) function: Activation,
+    pub(crate) aggregation: Aggregation,
 }
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
-pub(crate) struct Connection {
+impl Neuron {
+    /// Create a node computing `function(activation * aggregation(inputs) + bias)`
+    ///
+    /// # Arguments
+    ///
+    /// * `function` - Activation function applied last
+    /// * `activation` - Steepness the aggregated inputs are multiplied by
+    /// * `bias` - Added before applying `function`
+    /// * `aggregation` - How the weighted inputs are combined
+    pub fn new(
+        function: Activation,
+        activation: f32,
+        bias: f32,
+        aggregation: Aggregation,
+    ) -> Neuron {
+        Neuron {
+            activation,
+            bias,
+            function,
+            aggregation,
+        }
+    }
+
+    /// Steepness the aggregated inputs are multiplied by
+    pub fn activation(&self) -> f32 {
+        self.activation
+    }
+
+    /// Value added before applying the activation function
+    pub fn bias(&self) -> f32 {
+        self.bias
+    }
+
+    /// Activation function applied last
+    pub fn function(&self) -> Activation {
+        self.function
+    }
+
+    /// How the weighted inputs are combined
+    pub fn aggregation(&self) -> Aggregation {
+        self.aggregation
+    }
+
+    /// Set the steepness the aggregated inputs are multiplied by
+    pub fn set_activation(&mut self, activation: f32) {
+        self.activation = activation;
+    }
+
+    /// Set the value added before applying the activation function
+    pub fn set_bias(&mut self, bias: f32) {
+        self.bias = bias;
+    }
+
+    /// Set the activation function
+    pub fn set_function(&mut self, function: Activation) {
+        self.function = function;
+    }
+
+    /// Set how the weighted inputs are combined
+    pub fn set_aggregation(&mut self, aggregation: Aggregation) {
+        self.aggregation = aggregation;
+    }
+}
+
+impl Default for Neuron {
+    /// Sigmoid node with activation 4.9 and no bias, as created by `Neat`
+    fn default() -> Neuron {
+        Neuron {
+            activation: 4.9,
+            bias: 0.0,
+            function: Activation::Sigmoid,
+            aggregation: Aggregation::Sum,
+        }
+    }
+}
+
+/// Connection between two nodes of a `Genome`
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Connection {
     pub(crate) weight: f32,
     pub(crate) enabled: bool,
+    pub(crate) innovation: Option<u16>,
+}
+
+impl Connection {
+    /// Weight the start node's value is multiplied by
+    pub fn weight(&self) -> f32 {
+        self.weight
+    }
+
+    /// Disabled connections are kept for crossover but not used by `Network`
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Set the weight the start node's value is multiplied by
+    pub fn set_weight(&mut self, weight: f32) {
+        self.weight = weight;
+    }
+
+    /// Enable or disable the connection
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Historical marking shared by every genome in a `Neat` that has this connection, `None`
+    /// for connections added by `GenomeBuilder`
+    pub fn innovation(&self) -> Option<u16> {
+        self.innovation
+    }
+}
+
+/// Structural problem found by `GenomeBuilder` `build`, `Genome` `validate` or `Neat`
+/// `from_genomes`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GenomeError {
+    /// Connection starts or ends at a node that isn't in the genome
+    UnknownNode(u16),
+    /// Connection ends at an input
+    IntoInput(u16, u16),
+    /// Connection between the same two nodes added twice
+    DuplicateConnection(u16, u16),
+    /// Node added with the id of an existing node
+    DuplicateNode(u16),
+    /// Input or output id missing or not at its position, ids `0..inputs` must come first
+    /// followed by the outputs
+    MisplacedNode(u16),
 }
 
+impl fmt::Display for GenomeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GenomeError::UnknownNode(id) => write!(f, "unknown node {}", id),
+            GenomeError::IntoInput(start, end) => {
+                write!(f, "connection {} -> {} goes into an input", start, end)
+            }
+            GenomeError::DuplicateConnection(start, end) => {
+                write!(f, "duplicate connection {} -> {}", start, end)
+            }
+            GenomeError::DuplicateNode(id) => write!(f, "duplicate node {}", id),
+            GenomeError::MisplacedNode(id) => {
+                write!(f, "input or output {} is missing or out of place", id)
+            }
+        }
+    }
+}
+
+impl Error for GenomeError {}
+
+/// Network structure evolved by `Neat`. Nodes `0..inputs` are inputs, the next `outputs` nodes
+/// are outputs and any others are hidden. Build one with `GenomeBuilder` or get the most fit
+/// genome with `Neat` `best_genome`
 #[derive(Clone, Debug, Serialize, Deserialize)]
-pub(crate) struct Genome {
+pub struct Genome {
     pub(crate) inputs: u16,
     pub(crate) outputs: u16,
     pub(crate) nodes: IndexMap<u16, Neuron>,
@@ -29,10 +188,10 @@ impl Genome {
     pub(crate) fn new(inputs: u16, outputs: u16) -> Genome {
         let mut nodes = IndexMap::new();
         for i in 0..inputs {
-            nodes.insert(i, Neuron { activation: 4.9 });
+            nodes.insert(i, Neuron::default());
         }
         for i in inputs..inputs + outputs {
-            nodes.insert(i, Neuron { activation: 4.9 });
+            nodes.insert(i, Neuron::default());
         }
 
         Genome {
@@ -43,17 +202,259 @@ impl Genome {
         }
     }
 
+    /// Genome with the topology given by `NeatSettings` `initial_connection`. Hidden nodes use
+    /// the ids following the outputs
+    pub(crate) fn initial<R: Rng>(
+        inputs: u16,
+        outputs: u16,
+        innovations: &mut InnovationCounter,
+        settings: &NeatSettings,
+        rng: &mut R,
+    ) -> Genome {
+        let mut genome = Genome::new(inputs, outputs);
+
+        let (fraction, hidden, direct) = match settings.initial_connection {
+            InitialConnection::Unconnected => return genome,
+            InitialConnection::Single => {
+                if inputs > 0 && outputs > 0 {
+                    let start = rng.gen_range(0, inputs);
+                    let end = rng.gen_range(inputs, inputs + outputs);
+                    genome.connect((start, end), innovations, settings, rng);
+                }
+                return genome;
+            }
+            InitialConnection::Full { hidden, direct } => (1.0, hidden, direct),
+            InitialConnection::Partial {
+                fraction,
+                hidden,
+                direct,
+            } => (fraction, hidden, direct),
+        };
+
+        let first = inputs + outputs;
+        for id in first..first + hidden {
+            genome.nodes.insert(id, Neuron::default());
+        }
+
+        let mut connections = vec![];
+        for i in 0..inputs {
+            connections.extend((first..first + hidden).map(|h| (i, h)));
+        }
+        for h in first..first + hidden {
+            connections.extend((inputs..first).map(|o| (h, o)));
+        }
+        if hidden == 0 || direct {
+            for i in 0..inputs {
+                connections.extend((inputs..first).map(|o| (i, o)));
+            }
+        }
+
+        for connection in connections {
+            if rng.gen::<f32>() < fraction {
+                genome.connect(connection, innovations, settings, rng);
+            }
+        }
+
+        genome
+    }
+
+    fn connect<R: Rng>(
+        &mut self,
+        connection: (u16, u16),
+        innovations: &mut InnovationCounter,
+        settings: &NeatSettings,
+        rng: &mut R,
+    ) {
+        let weight = rng.gen_range(-settings.weight, settings.weight);
+        self.connections.insert(
+            connection,
+            Connection {
+                weight,
+                enabled: true,
+                innovation: Some(innovations.add(connection)),
+            },
+        );
+    }
+
+    /// Number of input nodes
+    pub fn inputs(&self) -> u16 {
+        self.inputs
+    }
+
+    /// Number of output nodes
+    pub fn outputs(&self) -> u16 {
+        self.outputs
+    }
+
+    /// Nodes with their ids, inputs first followed by outputs then hidden nodes
+    pub fn nodes(&self) -> impl Iterator<Item = (u16, &Neuron)> {
+        self.nodes.iter().map(|(id, neuron)| (*id, neuron))
+    }
+
+    /// Node with the given id
+    pub fn node(&self, id: u16) -> Option<&Neuron> {
+        self.nodes.get(&id)
+    }
+
+    /// Mutable node with the given id, for custom mutation operators
+    pub fn node_mut(&mut self, id: u16) -> Option<&mut Neuron> {
+        self.nodes.get_mut(&id)
+    }
+
+    /// Connections as `((start, end), connection)` including disabled ones, in the order they
+    /// were added
+    pub fn connections(&self) -> impl Iterator<Item = ((u16, u16), &Connection)> {
+        self.connections.iter().map(|(key, conn)| (*key, conn))
+    }
+
+    /// Connection from `start` to `end`
+    pub fn connection(&self, start: u16, end: u16) -> Option<&Connection> {
+        self.connections.get(&(start, end))
+    }
+
+    /// Mutable connection from `start` to `end`, for custom mutation operators
+    pub fn connection_mut(&mut self, start: u16, end: u16) -> Option<&mut Connection> {
+        self.connections.get_mut(&(start, end))
+    }
+
+    /// Add a hidden node, for custom mutation operators. It has no connections until some are
+    /// added with `insert_connection`
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Id of the new node, must not be used by another node. `Neat` numbers new nodes
+    ///   with innovation numbers counting up from the outputs, ids near `u16::MAX` avoid these
+    /// * `neuron` - The new node
+    pub fn insert_neuron(&mut self, id: u16, neuron: Neuron) -> Result<(), GenomeError> {
+        if self.nodes.contains_key(&id) {
+            return Err(GenomeError::DuplicateNode(id));
+        }
+
+        self.nodes.insert(id, neuron);
+        Ok(())
+    }
+
+    /// Add an enabled connection, for custom mutation operators. Like connections from
+    /// `GenomeBuilder` it has no innovation number so crossover treats it as disjoint
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - Node the connection reads from
+    /// * `end` - Node the connection goes into, can't be an input
+    /// * `weight` - Weight of the connection
+    pub fn insert_connection(
+        &mut self,
+        start: u16,
+        end: u16,
+        weight: f32,
+    ) -> Result<(), GenomeError> {
+        GenomeBuilder::check(&self.nodes, self.inputs, start, end)?;
+        if self.connections.contains_key(&(start, end)) {
+            return Err(GenomeError::DuplicateConnection(start, end));
+        }
+
+        self.connections.insert(
+            (start, end),
+            Connection {
+                weight,
+                enabled: true,
+                innovation: None,
+            },
+        );
+        Ok(())
+    }
+
+    /// Number of hidden nodes and enabled connections, the size of the `Network` it builds
+    pub fn size(&self) -> (usize, usize) {
+        let hidden = self.nodes.len() - (self.inputs + self.outputs) as usize;
+        let enabled = self.connections.values().filter(|c| c.enabled).count();
+
+        (hidden, enabled)
+    }
+
+    /// Checks the inputs and outputs are the first nodes in order of their ids, and every
+    /// connection is between nodes of the genome and doesn't go into an input
+    pub fn validate(&self) -> Result<(), GenomeError> {
+        for id in 0..self.inputs + self.outputs {
+            match self.nodes.get_index(id as usize) {
+                Some((key, _)) if *key == id => {}
+                _ => return Err(GenomeError::MisplacedNode(id)),
+            }
+        }
+
+        for (start, end) in self.connections.keys() {
+            GenomeBuilder::check(&self.nodes, self.inputs, *start, *end)?;
+        }
+
+        Ok(())
+    }
+
+    fn graph(&self) -> Graph {
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|(i, n)| (*i, n.activation, n.function, n.aggregation))
+            .collect();
+        let links = self
+            .connections
+            .iter()
+            .map(|((start, end), conn)| Link {
+                start: *start,
+                end: *end,
+                weight: conn.weight,
+                enabled: conn.enabled,
+            })
+            .collect();
+
+        Graph::new(self.inputs, self.outputs, nodes, links)
+    }
+
+    /// Graphviz DOT describing the genome, disabled connections are dashed when `show_disabled`
+    pub fn to_dot(&self, show_disabled: bool) -> String {
+        self.graph().to_dot(show_disabled)
+    }
+
+    /// SVG image of the genome, disabled connections are dashed when `show_disabled`
+    pub fn to_svg(&self, show_disabled: bool) -> String {
+        self.graph().to_svg(show_disabled)
+    }
+
+    /// JSON of the genome in the format described in `Network` `to_json`, including disabled
+    /// connections and innovation numbers
+    pub fn to_json(&self) -> String {
+        Document::from_genome(self).to_json()
+    }
+
+    /// Load a genome from JSON in the format described in `Network` `to_json`, see `Network`
+    /// `from_json`
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - JSON written by `to_json` or by hand
+    pub fn from_json(json: &str) -> serde_json::Result<Genome> {
+        let document: Document = serde_json::from_str(json)?;
+        document.into_genome()
+    }
+
+    /// Load a genome trained with neat-python, see `Network` `from_neat_python`
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - Genome dumped by the function in `Network` `from_neat_python`
+    pub fn from_neat_python(json: &str) -> serde_json::Result<Genome> {
+        neat_python::genome(json)
+    }
+
     fn is_output(&self, index: usize) -> bool {
         index >= self.inputs as usize && index < (self.inputs + self.outputs) as usize
     }
 
-    pub(crate) fn add_connection(
+    pub(crate) fn add_connection<R: Rng>(
         &mut self,
         innovations: &mut InnovationCounter,
         settings: &NeatSettings,
+        rng: &mut R,
     ) -> bool {
-        let mut rng = rand::thread_rng();
-
         let input = rng.gen_range(0, self.nodes.len());
         let output = rng.gen_range(self.inputs as usize, self.nodes.len());
 
@@ -76,25 +477,30 @@ impl Genome {
             return true;
         }
 
-        self.connections.insert(
-            connection,
-            Connection {
-                weight: rng.gen_range(-settings.weight, settings.weight),
-                enabled: true,
-            },
-        );
-        innovations.add(connection);
+        self.connect(connection, innovations, settings, rng);
 
         true
     }
 
-    fn add_node(&mut self, innovations: &mut InnovationCounter) {
+    /// Activation function for a new hidden node from `NeatSettings` `activation_functions`
+    fn function<R: Rng>(settings: &NeatSettings, rng: &mut R) -> Activation {
+        match settings.activation_functions.as_slice() {
+            [] => Activation::default(),
+            [function] => *function,
+            functions => functions[rng.gen_range(0, functions.len())],
+        }
+    }
+
+    fn add_node<R: Rng>(
+        &mut self,
+        innovations: &mut InnovationCounter,
+        settings: &NeatSettings,
+        rng: &mut R,
+    ) {
         if self.connections.is_empty() {
             return;
         }
 
-        let mut rng = rand::thread_rng();
-
         let (connection, info) = self
             .connections
             .get_index_mut(rng.gen_range(0, self.connections.len()))
@@ -108,30 +514,32 @@ impl Genome {
         let (start, end) = *connection;
         let weight = info.weight;
 
-        innovations.add((start, innovation));
         self.connections.insert(
             (start, innovation),
             Connection {
                 weight: 1.0,
                 enabled: true,
+                innovation: Some(innovations.add((start, innovation))),
             },
         );
 
-        innovations.add((innovation, end));
         self.connections.insert(
             (innovation, end),
             Connection {
                 weight,
                 enabled: true,
+                innovation: Some(innovations.add((innovation, end))),
             },
         );
 
-        self.nodes.insert(innovation, Neuron { activation: 4.9 });
+        let neuron = Neuron {
+            function: Genome::function(settings, rng),
+            ..Neuron::default()
+        };
+        self.nodes.insert(innovation, neuron);
     }
 
-    fn mutate_connections(&mut self, settings: &NeatSettings) {
-        let mut rng = rand::thread_rng();
-
+    fn mutate_connections<R: Rng>(&mut self, settings: &NeatSettings, rng: &mut R) {
         for (_, info) in self.connections.iter_mut().filter(|(_, i)| i.enabled) {
             if rng.gen::<f32>() < settings.weight_mutate_rate {
                 info.weight += rng.gen_range(-settings.weight_mutate, settings.weight_mutate);
@@ -139,47 +547,103 @@ impl Genome {
         }
     }
 
-    fn mutate_nodes(&mut self, settings: &NeatSettings) {
-        let mut rng = rand::thread_rng();
-
+    fn mutate_nodes<R: Rng>(&mut self, settings: &NeatSettings, rng: &mut R) {
         for (_, node) in self.nodes.iter_mut() {
             if rng.gen::<f32>() <= settings.activation_mutate_rate {
                 node.activation +=
                     rng.gen_range(-settings.activation_mutate, settings.activation_mutate);
             }
         }
-    }
 
-    pub(crate) fn mutate(&mut self, innovations: &mut InnovationCounter, settings: &NeatSettings) {
-        let mut rng = rand::thread_rng();
+        if settings.function_mutate_rate > 0.0 {
+            let fixed = (self.inputs + self.outputs) as usize;
+            for (_, node) in self.nodes.iter_mut().skip(fixed) {
+                if rng.gen::<f32>() < settings.function_mutate_rate {
+                    node.function = Genome::function(settings, rng);
+                }
+            }
+        }
+    }
 
+    pub(crate) fn mutate<R: Rng>(
+        &mut self,
+        innovations: &mut InnovationCounter,
+        settings: &NeatSettings,
+        rng: &mut R,
+    ) {
         if rng.gen::<f32>() <= settings.add_connection_rate {
-            self.add_connection(innovations, settings);
+            self.add_connection(innovations, settings, rng);
         }
 
         if rng.gen::<f32>() <= settings.add_node_rate {
-            self.add_node(innovations);
+            self.add_node(innovations, settings, rng);
         }
 
-        self.mutate_connections(&settings);
+        self.mutate_connections(settings, rng);
 
-        self.mutate_nodes(&settings);
+        self.mutate_nodes(settings, rng);
     }
 
-    pub(crate) fn cross(better: &Genome, worse: &Genome) -> Genome {
+    /// Disables a random enabled connection. The gene is kept so crossover and `distance` still
+    /// match it by innovation number with the genomes that have it
+    fn remove_connection<R: Rng>(&mut self, rng: &mut R) {
+        let enabled: Vec<(u16, u16)> = self
+            .connections
+            .iter()
+            .filter(|(_, info)| info.enabled)
+            .map(|(connection, _)| *connection)
+            .collect();
+
+        if let Some(connection) = enabled.choose(rng) {
+            self.connections[connection].enabled = false;
+        }
+    }
+
+    /// Removes a random hidden node and every connection into or out of it. This is the only
+    /// time disabled genes are dropped, as they can't be enabled again without the node
+    fn remove_node<R: Rng>(&mut self, rng: &mut R) {
+        let fixed = (self.inputs + self.outputs) as usize;
+        if self.nodes.len() == fixed {
+            return;
+        }
+
+        let index = rng.gen_range(fixed, self.nodes.len());
+        let (id, _) = self.nodes.shift_remove_index(index).unwrap();
+        self.connections
+            .retain(|(start, end), _| *start != id && *end != id);
+    }
+
+    /// Mutation used while simplifying, removes structure instead of adding it
+    pub(crate) fn prune<R: Rng>(&mut self, settings: &NeatSettings, rng: &mut R) {
+        if rng.gen::<f32>() <= settings.remove_connection_rate {
+            self.remove_connection(rng);
+        }
+
+        if rng.gen::<f32>() <= settings.remove_node_rate {
+            self.remove_node(rng);
+        }
+
+        self.mutate_connections(settings, rng);
+
+        self.mutate_nodes(settings, rng);
+    }
+
+    pub(crate) fn cross<R: Rng>(better: &Genome, worse: &Genome, rng: &mut R) -> Genome {
         assert_eq!(better.inputs, worse.inputs);
         assert_eq!(better.outputs, worse.outputs);
 
         let mut child = Genome::new(better.inputs, better.outputs);
+        let fixed = (better.inputs + better.outputs) as usize;
+        for (id, neuron) in better.nodes.iter().take(fixed) {
+            child.nodes[id] = *neuron;
+        }
 
         for (connection, info) in better.connections.iter() {
             let (start, end) = connection;
 
             child.connections.insert(
                 *connection,
-                if worse.connections.contains_key(connection)
-                    && rand::thread_rng().gen::<f32>() < 0.5
-                {
+                if worse.connections.contains_key(connection) && rng.gen::<f32>() < 0.5 {
                     if !child.nodes.contains_key(start) {
                         child.nodes.insert(*start, *worse.nodes.get(start).unwrap());
                     }
@@ -208,42 +672,211 @@ impl Genome {
         child
     }
 
-    pub(crate) fn same_species(first: &Genome, second: &Genome, settings: &NeatSettings) -> bool {
-        let mut c_diff = 0.0;
-        let mut w_diff = 0.0;
+    /// Compatibility distance used to group genomes into species,
+    /// `excess_diff * E / N + connections_diff * D / N + weight_diff * W + node_diff * M / K`.
+    /// `E` and `D` are the excess and disjoint connections, told apart by their innovation
+    /// numbers, `N` is the connection count of the larger genome and `W` the average weight
+    /// difference of matching connections. `M` sums the activation and bias differences of
+    /// matching nodes, plus one for each different function or aggregation and each node only
+    /// one genome has, over `K` the node count of the larger genome
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - Genome to compare with
+    /// * `settings` - Weights given to each difference
+    pub fn distance(&self, other: &Genome, settings: &NeatSettings) -> f32 {
+        let (excess, disjoint, weights, matching) = Genome::connection_diff(self, other);
+        let (other_excess, other_disjoint, _, _) = Genome::connection_diff(other, self);
+        let excess = (excess + other_excess) as f32;
+        let disjoint = (disjoint + other_disjoint) as f32;
 
-        for (connection, f_info) in first.connections.iter() {
-            if let Some(s_info) = second.connections.get(connection) {
-                w_diff += (f_info.weight - s_info.weight).abs();
+        let size = max(self.connections.len(), other.connections.len());
+        let connection_diff = if size != 0 {
+            (excess * settings.excess_diff + disjoint * settings.connections_diff) / size as f32
+        } else {
+            0.0
+        };
+
+        let weight_diff = if matching != 0 {
+            weights / matching as f32 * settings.weight_diff
+        } else {
+            0.0
+        };
+
+        let mut n_diff = 0.0;
+        for (id, node) in self.nodes.iter() {
+            if let Some(other_node) = other.nodes.get(id) {
+                n_diff += (node.activation - other_node.activation).abs();
+                n_diff += (node.bias - other_node.bias).abs();
+                if node.function != other_node.function {
+                    n_diff += 1.0;
+                }
+                if node.aggregation != other_node.aggregation {
+                    n_diff += 1.0;
+                }
             } else {
-                c_diff += 1.0;
+                n_diff += 1.0;
             }
         }
+        n_diff += other
+            .nodes
+            .keys()
+            .filter(|id| !self.nodes.contains_key(*id))
+            .count() as f32;
+
+        let size = max(self.nodes.len(), other.nodes.len());
+        let node_diff = if size != 0 {
+            (n_diff * settings.node_diff) / size as f32
+        } else {
+            0.0
+        };
+
+        connection_diff + weight_diff + node_diff
+    }
 
-        c_diff += second.connections.keys().fold(0.0, |acc, conn| {
-            if first.connections.get(conn).is_none() {
-                acc + 1.0
+    /// Counts connections of `first` missing from `second` as excess when their innovation is
+    /// past every innovation in `second` and as disjoint otherwise, returns these with the sum of
+    /// weight differences and count of matching connections
+    fn connection_diff(first: &Genome, second: &Genome) -> (usize, usize, f32, usize) {
+        let newest = second
+            .connections
+            .values()
+            .filter_map(|c| c.innovation)
+            .max();
+
+        let mut excess = 0;
+        let mut disjoint = 0;
+        let mut weights = 0.0;
+        let mut matching = 0;
+
+        for (connection, f_info) in first.connections.iter() {
+            if let Some(s_info) = second.connections.get(connection) {
+                weights += (f_info.weight - s_info.weight).abs();
+                matching += 1;
             } else {
-                acc
+                match (f_info.innovation, newest) {
+                    (Some(innovation), Some(newest)) if innovation > newest => excess += 1,
+                    _ => disjoint += 1,
+                }
             }
-        });
+        }
 
-        let size = max(first.connections.len(), second.connections.len());
-        let connection_diff = if size != 0 {
-            (c_diff * settings.connections_diff) / size as f32
-        } else {
-            0.0
+        (excess, disjoint, weights, matching)
+    }
+}
+
+/// Builds a `Genome` by hand, such as a designed starting topology
+///
+/// # Example
+///
+/// ```
+/// use neat::{GenomeBuilder, Neuron};
+///
+/// let genome = GenomeBuilder::new(2, 1)
+///     .neuron(3, Neuron::default())
+///     .connection(0, 3, 1.0)
+///     .connection(1, 3, -1.0)
+///     .connection(3, 2, 2.0)
+///     .build()
+///     .unwrap();
+/// ```
+pub struct GenomeBuilder {
+    genome: Genome,
+    /// Ids given to `neuron`
+    named: HashSet<u16>,
+    duplicate: Option<GenomeError>,
+}
+
+impl GenomeBuilder {
+    /// Start a genome with the given inputs and outputs using `Neuron` `default` and no
+    /// connections
+    ///
+    /// # Arguments
+    ///
+    /// * `inputs` - Number of inputs, with ids `0..inputs`
+    /// * `outputs` - Number of outputs, with ids following the inputs
+    pub fn new(inputs: u16, outputs: u16) -> GenomeBuilder {
+        GenomeBuilder {
+            genome: Genome::new(inputs, outputs),
+            named: HashSet::new(),
+            duplicate: None,
+        }
+    }
+
+    /// Set an input or output node, or add a hidden node if `id` is after the outputs. Giving
+    /// the same id twice is an error
+    pub fn neuron(mut self, id: u16, neuron: Neuron) -> GenomeBuilder {
+        if !self.named.insert(id) {
+            self.duplicate.get_or_insert(GenomeError::DuplicateNode(id));
+        }
+
+        self.genome.nodes.insert(id, neuron);
+        self
+    }
+
+    /// Add an enabled connection
+    pub fn connection(self, start: u16, end: u16, weight: f32) -> GenomeBuilder {
+        self.add(start, end, weight, true)
+    }
+
+    /// Add a disabled connection, kept for crossover but not used by `Network`
+    pub fn disabled_connection(self, start: u16, end: u16, weight: f32) -> GenomeBuilder {
+        self.add(start, end, weight, false)
+    }
+
+    fn add(mut self, start: u16, end: u16, weight: f32, enabled: bool) -> GenomeBuilder {
+        let connection = Connection {
+            weight,
+            enabled,
+            innovation: None,
         };
 
-        let weight_diff = w_diff * settings.weight_diff;
+        if self
+            .genome
+            .connections
+            .insert((start, end), connection)
+            .is_some()
+        {
+            self.duplicate
+                .get_or_insert(GenomeError::DuplicateConnection(start, end));
+        }
+        self
+    }
+
+    fn check(
+        nodes: &IndexMap<u16, Neuron>,
+        inputs: u16,
+        start: u16,
+        end: u16,
+    ) -> Result<(), GenomeError> {
+        for id in [start, end] {
+            if !nodes.contains_key(&id) {
+                return Err(GenomeError::UnknownNode(id));
+            }
+        }
+
+        if end < inputs {
+            return Err(GenomeError::IntoInput(start, end));
+        }
 
-        (connection_diff + weight_diff) < settings.species_threshold
+        Ok(())
+    }
+
+    /// Returns the genome or the first structural problem found
+    pub fn build(self) -> Result<Genome, GenomeError> {
+        if let Some(err) = self.duplicate {
+            return Err(err);
+        }
+
+        self.genome.validate()?;
+        Ok(self.genome)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::network::Network;
 
     #[test]
     fn test_same_species() {
@@ -261,6 +894,7 @@ mod tests {
             species_threshold: 1.0,
             feedforward: true,
             reset_fitness: false,
+            ..NeatSettings::default()
         };
 
         let mut first = Genome::new(1, 2);
@@ -269,6 +903,7 @@ mod tests {
             Connection {
                 weight: 0.5,
                 enabled: true,
+                innovation: None,
             },
         );
         let mut second = Genome::new(1, 2);
@@ -277,16 +912,18 @@ mod tests {
             Connection {
                 weight: 1.5,
                 enabled: true,
+                innovation: None,
             },
         );
 
-        assert!(Genome::same_species(&first, &second, &settings));
+        assert!(first.distance(&second, &settings) < settings.species_threshold);
 
         second.connections.insert(
             (0, 1),
             Connection {
                 weight: 1.5,
                 enabled: true,
+                innovation: None,
             },
         );
         second.connections.insert(
@@ -294,6 +931,7 @@ mod tests {
             Connection {
                 weight: 1.5,
                 enabled: true,
+                innovation: None,
             },
         );
         second.connections.insert(
@@ -301,6 +939,7 @@ mod tests {
             Connection {
                 weight: 1.5,
                 enabled: true,
+                innovation: None,
             },
         );
         first.connections.insert(
@@ -308,9 +947,266 @@ mod tests {
             Connection {
                 weight: 1.5,
                 enabled: true,
+                innovation: None,
+            },
+        );
+
+        assert!(first.distance(&second, &settings) >= settings.species_threshold);
+    }
+
+    #[test]
+    fn test_distance() {
+        let settings = NeatSettings {
+            excess_diff: 1.0,
+            connections_diff: 0.5,
+            weight_diff: 0.4,
+            node_diff: 0.3,
+            ..NeatSettings::default()
+        };
+        let connection = |weight, innovation| Connection {
+            weight,
+            enabled: true,
+            innovation: Some(innovation),
+        };
+
+        let mut first = Genome::new(2, 2);
+        first.connections.insert((0, 2), connection(1.0, 3));
+        first.connections.insert((1, 2), connection(1.0, 4));
+        let mut second = first.clone();
+        assert_eq!(first.distance(&second, &settings), 0.0);
+
+        // Two disjoint and one excess connection out of 3, the matching weight differs by 1.0
+        first.connections.insert((0, 3), connection(1.0, 5));
+        second.connections.get_mut(&(0, 2)).unwrap().weight = 2.0;
+        second.connections.shift_remove(&(1, 2));
+        second.connections.insert((1, 3), connection(1.0, 6));
+        let expected = (1.0 + 2.0 * 0.5) / 3.0 + 0.4 * 1.0;
+        assert!((first.distance(&second, &settings) - expected).abs() < 1e-6);
+        assert_eq!(
+            first.distance(&second, &settings),
+            second.distance(&first, &settings)
+        );
+
+        // A hidden node only in one genome and a different bias and function
+        let mut third = first.clone();
+        third.nodes.insert(4, Neuron::default());
+        third.nodes.get_mut(&2).unwrap().bias = 0.5;
+        third.nodes.get_mut(&2).unwrap().function = Activation::Tanh;
+        let expected = 0.3 * (1.0 + 0.5 + 1.0) / 5.0;
+        assert!((first.distance(&third, &settings) - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_builder() {
+        let genome = GenomeBuilder::new(2, 1)
+            .neuron(
+                3,
+                Neuron::new(Activation::Sigmoid, 4.9, 1.0, Aggregation::Sum),
+            )
+            .connection(0, 3, 1.0)
+            .disabled_connection(1, 2, -1.0)
+            .connection(3, 2, 2.0)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            genome.nodes().map(|(id, _)| id).collect::<Vec<_>>(),
+            vec![0, 1, 2, 3]
+        );
+        assert_eq!(genome.node(3).unwrap().bias(), 1.0);
+        assert!(!genome.connection(1, 2).unwrap().enabled());
+        assert_eq!(genome.connections().count(), 3);
+        assert_eq!(genome.size(), (1, 2));
+
+        let mut genome = genome;
+        let mut neuron = Neuron::default();
+        neuron.set_function(Activation::Tanh);
+        genome.insert_neuron(4, neuron).unwrap();
+        genome.insert_connection(1, 4, 0.5).unwrap();
+        genome.insert_connection(4, 2, 1.5).unwrap();
+        genome.connection_mut(1, 2).unwrap().set_enabled(true);
+        genome.node_mut(3).unwrap().set_bias(-1.0);
+        assert_eq!(genome.node(4).unwrap().function(), Activation::Tanh);
+        assert_eq!(genome.node(3).unwrap().bias(), -1.0);
+        assert_eq!(genome.connection(4, 2).unwrap().weight(), 1.5);
+        assert_eq!(genome.connection(4, 2).unwrap().innovation(), None);
+        assert_eq!(genome.size(), (2, 5));
+        assert_eq!(
+            genome.insert_neuron(3, neuron),
+            Err(GenomeError::DuplicateNode(3))
+        );
+        assert_eq!(
+            genome.insert_connection(4, 2, 1.0),
+            Err(GenomeError::DuplicateConnection(4, 2))
+        );
+        assert_eq!(
+            genome.insert_connection(4, 0, 1.0),
+            Err(GenomeError::IntoInput(4, 0))
+        );
+        assert_eq!(
+            genome.insert_connection(5, 2, 1.0),
+            Err(GenomeError::UnknownNode(5))
+        );
+        assert!(Network::new(genome).is_ok());
+
+        let builder = || GenomeBuilder::new(2, 1).connection(0, 2, 1.0);
+        assert_eq!(
+            builder().connection(0, 4, 1.0).build().unwrap_err(),
+            GenomeError::UnknownNode(4)
+        );
+        assert_eq!(
+            builder().connection(2, 1, 1.0).build().unwrap_err(),
+            GenomeError::IntoInput(2, 1)
+        );
+        assert_eq!(
+            builder()
+                .disabled_connection(0, 2, 1.0)
+                .build()
+                .unwrap_err(),
+            GenomeError::DuplicateConnection(0, 2)
+        );
+        assert_eq!(
+            builder()
+                .neuron(3, Neuron::default())
+                .neuron(3, Neuron::default())
+                .build()
+                .unwrap_err(),
+            GenomeError::DuplicateNode(3)
+        );
+
+        // Inputs and outputs must keep their positions
+        let mut genome = builder().build().unwrap();
+        genome.nodes.swap_indices(0, 1);
+        assert_eq!(genome.validate(), Err(GenomeError::MisplacedNode(0)));
+        genome.nodes.shift_remove(&0);
+        assert_eq!(genome.validate(), Err(GenomeError::MisplacedNode(0)));
+        let mut genome = builder().build().unwrap();
+        genome.nodes.shift_remove(&2);
+        assert_eq!(genome.validate(), Err(GenomeError::MisplacedNode(2)));
+    }
+
+    #[test]
+    fn test_prune() {
+        let settings = NeatSettings {
+            remove_connection_rate: 1.0,
+            remove_node_rate: 1.0,
+            ..NeatSettings::default()
+        };
+        let mut rng = rand::thread_rng();
+        let mut genome = GenomeBuilder::new(2, 1)
+            .neuron(3, Neuron::default())
+            .neuron(4, Neuron::default())
+            .connection(0, 3, 1.0)
+            .connection(3, 2, 1.0)
+            .connection(1, 4, 1.0)
+            .connection(4, 2, 1.0)
+            .disabled_connection(0, 2, 1.0)
+            .build()
+            .unwrap();
+
+        // Removed connections are disabled, keeping their genes
+        let mut disabled = genome.clone();
+        disabled.prune(
+            &NeatSettings {
+                remove_node_rate: 0.0,
+                ..settings.clone()
             },
+            &mut rng,
         );
+        assert_eq!(disabled.size(), (2, 3));
+        assert_eq!(disabled.connections().count(), 5);
+
+        genome.prune(&settings, &mut rng);
+        let (hidden, enabled) = genome.size();
+        assert_eq!(hidden, 1);
+        assert!(enabled == 1 || enabled == 2);
+        assert_eq!(genome.connections().count(), 3);
+        genome.validate().unwrap();
+
+        genome.prune(&settings, &mut rng);
+        assert_eq!(genome.size(), (0, 0));
+        assert!(genome.connection(0, 2).is_some());
+    }
+
+    #[test]
+    fn test_innovation() {
+        let settings = NeatSettings::default();
+        let mut innovations = InnovationCounter::new(3);
+        let mut rng = rand::thread_rng();
+
+        let mut genome = Genome::new(2, 1);
+        while !genome.add_connection(&mut innovations, &settings, &mut rng) {}
+        genome.add_node(&mut innovations, &settings, &mut rng);
+        genome.validate().unwrap();
+
+        for ((start, end), connection) in genome.connections() {
+            assert_eq!(connection.innovation(), innovations.get((start, end)));
+            assert!(connection.innovation().is_some());
+        }
 
-        assert!(!Genome::same_species(&first, &second, &settings));
+        let copy = Genome::from_json(&genome.to_json()).unwrap();
+        assert_eq!(
+            copy.connections().collect::<Vec<_>>(),
+            genome.connections().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_initial() {
+        let mut innovations = InnovationCounter::new(3 + 2 + 4);
+        let mut rng = rand::thread_rng();
+        let mut initial = |initial_connection| {
+            let settings = NeatSettings {
+                initial_connection,
+                ..NeatSettings::default()
+            };
+            let genome = Genome::initial(3, 2, &mut innovations, &settings, &mut rng);
+            genome.validate().unwrap();
+            genome
+        };
+
+        assert_eq!(initial(InitialConnection::Unconnected).connections.len(), 0);
+
+        let single = initial(InitialConnection::Single);
+        let (start, end) = *single.connections.keys().next().unwrap();
+        assert_eq!(single.connections.len(), 1);
+        assert!(start < 3 && (3..5).contains(&end));
+
+        let direct = initial(InitialConnection::Full {
+            hidden: 0,
+            direct: false,
+        });
+        assert_eq!(direct.connections.len(), 6);
+
+        let hidden = initial(InitialConnection::Full {
+            hidden: 4,
+            direct: false,
+        });
+        assert_eq!(hidden.nodes.len(), 9);
+        assert_eq!(hidden.connections.len(), 12 + 8);
+        assert!(hidden.connection(0, 3).is_none());
+
+        let both = initial(InitialConnection::Full {
+            hidden: 4,
+            direct: true,
+        });
+        assert_eq!(both.connections.len(), 12 + 8 + 6);
+        assert_eq!(
+            both.connection(0, 3).unwrap().innovation(),
+            direct.connection(0, 3).unwrap().innovation()
+        );
+        assert!(both.nodes.keys().all(|id| *id < 9));
+        assert!(both
+            .connections
+            .values()
+            .all(|c| c.innovation().unwrap() >= 9));
+
+        let partial = initial(InitialConnection::Partial {
+            fraction: 0.0,
+            hidden: 4,
+            direct: true,
+        });
+        assert_eq!(partial.nodes.len(), 9);
+        assert_eq!(partial.connections.len(), 0);
     }
 }
@@ -0,0 +1,555 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use super::genome::{Connection, Genome, Neuron};
+use super::network::Network;
+
+/// Positions of the nodes of a network decoded from a CPPN by HyperNEAT, in layers from inputs
+/// to outputs with each layer fully connected to the next. The CPPN is given the coordinates of
+/// both ends of a connection followed by a constant 1.0 and its first output sets the weight
+///
+/// # Example
+///
+/// ```ignore
+/// use neat::{Neat, NeatSettings, Substrate};
+///
+/// let inputs = (0..9).map(|i| vec![(i % 3) as f32 - 1.0, (i / 3) as f32 - 1.0, -1.0]).collect();
+/// let hidden = (0..4).map(|i| vec![(i % 2) as f32 - 0.5, (i / 2) as f32 - 0.5, 0.0]).collect();
+/// let outputs = vec![vec![0.0, 0.0, 1.0]];
+///
+/// let substrate = Substrate::new(vec![inputs, hidden, outputs]);
+/// let mut neat = Neat::<TaskImplementation>::hyperneat(100, substrate, NeatSettings::default());
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Substrate {
+    layers: Vec<Vec<Vec<f32>>>,
+    dimensions: usize,
+    /// CPPN outputs with a smaller magnitude don't create a connection [0.0 - 1.0)
+    pub threshold: f32,
+    /// Weight of a connection when the CPPN output is 1.0 or -1.0
+    pub weight_max: f32,
+    /// Use the second CPPN output for link expression (LEO), connections are only created when
+    /// it is positive instead of when the weight output is above `threshold`
+    pub leo: bool,
+}
+
+impl Substrate {
+    /// Create substrate with `threshold` 0.2, `weight_max` 3.0 and without `leo`
+    ///
+    /// # Arguments
+    ///
+    /// * `layers` - Coordinates of the nodes in each layer, the first layer is the network
+    ///   inputs and the last its outputs. All coordinates must have 2 or 3 dimensions
+    pub fn new(layers: Vec<Vec<Vec<f32>>>) -> Substrate {
+        assert!(layers.len() >= 2, "Substrate needs input and output layers");
+
+        let dimensions = layers[0].first().map_or(2, |c| c.len());
+        assert!(dimensions == 2 || dimensions == 3);
+        assert!(
+            layers.iter().flatten().all(|c| c.len() == dimensions),
+            "All coordinates must have the same dimensions"
+        );
+
+        Substrate {
+            layers,
+            dimensions,
+            threshold: 0.2,
+            weight_max: 3.0,
+            leo: false,
+        }
+    }
+
+    /// Number of inputs a CPPN for this substrate needs
+    pub fn cppn_inputs(&self) -> u16 {
+        (self.dimensions * 2 + 1) as u16
+    }
+
+    /// Number of outputs a CPPN for this substrate needs
+    pub fn cppn_outputs(&self) -> u16 {
+        if self.leo {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Weight of a connection from the CPPN outputs, `None` if it isn't expressed
+    fn weight(&self, outputs: &[f32]) -> Option<f32> {
+        let weight = outputs[0].clamp(-1.0, 1.0);
+
+        if self.leo {
+            if outputs[1] <= 0.0 {
+                return None;
+            }
+            return Some(weight * self.weight_max);
+        }
+
+        scale(weight, self.threshold, self.weight_max)
+    }
+
+    /// Queries the CPPN for every connection between consecutive layers and builds the network
+    /// they describe. Inputs and outputs follow the order of the first and last layers
+    ///
+    /// # Arguments
+    ///
+    /// * `cppn` - Genome with `cppn_inputs` inputs and `cppn_outputs` outputs
+    pub fn network(&self, cppn: &Genome) -> Network {
+        assert_eq!(cppn.inputs, self.cppn_inputs());
+        assert_eq!(cppn.outputs, self.cppn_outputs());
+
+        let last = self.layers.len() - 1;
+        let inputs = self.layers[0].len() as u16;
+        let outputs = self.layers[last].len() as u16;
+
+        // First id of each layer, following the `Genome` layout of inputs, outputs then hidden
+        let mut genome = Genome::new(inputs, outputs);
+        let mut ids = vec![0; self.layers.len()];
+        ids[last] = inputs;
+        for (l, layer) in self.layers.iter().enumerate().take(last).skip(1) {
+            ids[l] = genome.nodes.len() as u16;
+            for _ in layer {
+                let id = genome.nodes.len() as u16;
+                genome.nodes.insert(id, Neuron::default());
+            }
+        }
+
+        let mut network = Network::from_genome(cppn.clone());
+        for l in 0..last {
+            for (i, start) in self.layers[l].iter().enumerate() {
+                for (j, end) in self.layers[l + 1].iter().enumerate() {
+                    let mut query = start.clone();
+                    query.extend(end);
+                    query.push(1.0);
+
+                    network.reset();
+                    network.prop(query);
+
+                    if let Some(weight) = self.weight(&network.get_outputs()) {
+                        let key = (ids[l] + i as u16, ids[l + 1] + j as u16);
+                        let connection = Connection {
+                            weight,
+                            enabled: true,
+                            innovation: None,
+                        };
+                        genome.connections.insert(key, connection);
+                    }
+                }
+            }
+        }
+
+        Network::from_genome(genome)
+    }
+}
+
+/// Weight for a CPPN output, `None` if its magnitude isn't above `threshold`
+fn scale(output: f32, threshold: f32, weight_max: f32) -> Option<f32> {
+    let output = output.clamp(-1.0, 1.0);
+    if output.abs() <= threshold {
+        return None;
+    }
+
+    let scaled = (output.abs() - threshold) / (1.0 - threshold);
+    Some(output.signum() * scaled * weight_max)
+}
+
+/// Square region of the quadtree searched by ES-HyperNEAT with the CPPN weight at its center
+struct Quad {
+    x: f32,
+    y: f32,
+    width: f32,
+    weight: f32,
+    children: Vec<Quad>,
+}
+
+impl Quad {
+    fn leaves(&self, weights: &mut Vec<f32>) {
+        for child in self.children.iter() {
+            if child.children.is_empty() {
+                weights.push(child.weight);
+            } else {
+                child.leaves(weights);
+            }
+        }
+    }
+
+    fn variance(&self) -> f32 {
+        let mut weights = vec![];
+        self.leaves(&mut weights);
+        if weights.is_empty() {
+            return 0.0;
+        }
+
+        let mean = weights.iter().sum::<f32>() / weights.len() as f32;
+        weights.iter().map(|w| (w - mean).powi(2)).sum::<f32>() / weights.len() as f32
+    }
+}
+
+type Point = (f32, f32);
+
+/// Connection found by ES-HyperNEAT as `(start, end, weight)`
+type Link = (Point, Point, f32);
+
+fn key(point: Point) -> (u32, u32) {
+    (point.0.to_bits(), point.1.to_bits())
+}
+
+/// Evolvable substrate for ES-HyperNEAT, only input and output positions are given and hidden
+/// nodes are placed where the CPPN weight pattern has the most information. The search covers
+/// `[-1, 1]` in both dimensions with a quadtree that is divided where the weights vary, then
+/// connections are created at points whose weight differs from their neighbours by more than
+/// `band_threshold`. The CPPN is given `(x1, y1, x2, y2, 1.0)` and its output sets the weight
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EvolvableSubstrate {
+    inputs: Vec<Point>,
+    outputs: Vec<Point>,
+    /// Depth the quadtree is always divided to
+    pub initial_depth: u8,
+    /// Depth the quadtree is never divided past
+    pub max_depth: u8,
+    /// Variance of the weights in a region above which it is divided further
+    pub division_threshold: f32,
+    /// Variance of the weights in a region below which connections are extracted from it
+    pub variance_threshold: f32,
+    /// Minimum difference between the weight at a point and its neighbours for a connection to
+    /// be created there
+    pub band_threshold: f32,
+    /// Number of times the search is repeated from newly found hidden nodes
+    pub iteration_level: u8,
+    /// CPPN outputs with a smaller magnitude give a weight of 0.0 [0.0 - 1.0)
+    pub threshold: f32,
+    /// Weight of a connection when the CPPN output is 1.0 or -1.0
+    pub weight_max: f32,
+}
+
+impl EvolvableSubstrate {
+    /// Create evolvable substrate with `initial_depth` 2, `max_depth` 4, `division_threshold`
+    /// 0.03, `variance_threshold` 0.03, `band_threshold` 0.3, `iteration_level` 1, `threshold`
+    /// 0.2 and `weight_max` 5.0
+    ///
+    /// # Arguments
+    ///
+    /// * `inputs` - Positions of the network inputs in `[-1, 1]`
+    /// * `outputs` - Positions of the network outputs in `[-1, 1]`
+    pub fn new(inputs: Vec<(f32, f32)>, outputs: Vec<(f32, f32)>) -> EvolvableSubstrate {
+        EvolvableSubstrate {
+            inputs,
+            outputs,
+            initial_depth: 2,
+            max_depth: 4,
+            division_threshold: 0.03,
+            variance_threshold: 0.03,
+            band_threshold: 0.3,
+            iteration_level: 1,
+            threshold: 0.2,
+            weight_max: 5.0,
+        }
+    }
+
+    fn query(&self, cppn: &mut Network, start: Point, end: Point) -> f32 {
+        cppn.reset();
+        cppn.prop(vec![start.0, start.1, end.0, end.1, 1.0]);
+        scale(cppn.get_outputs()[0], self.threshold, self.weight_max).unwrap_or(0.0)
+    }
+
+    /// Weight of the connection between `point` and the node at `from`, which is the start of
+    /// the connection when `outgoing`
+    fn weight(&self, cppn: &mut Network, from: Point, point: Point, outgoing: bool) -> f32 {
+        if outgoing {
+            self.query(cppn, from, point)
+        } else {
+            self.query(cppn, point, from)
+        }
+    }
+
+    fn divide(&self, cppn: &mut Network, from: Point, quad: &mut Quad, level: u8, outgoing: bool) {
+        let width = quad.width / 2.0;
+        for (dx, dy) in [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)] {
+            let point = (quad.x + dx * width, quad.y + dy * width);
+            quad.children.push(Quad {
+                x: point.0,
+                y: point.1,
+                width,
+                weight: self.weight(cppn, from, point, outgoing),
+                children: vec![],
+            });
+        }
+
+        let level = level + 1;
+        if level < self.initial_depth
+            || (level < self.max_depth && quad.variance() > self.division_threshold)
+        {
+            for child in quad.children.iter_mut() {
+                self.divide(cppn, from, child, level, outgoing);
+            }
+        }
+    }
+
+    fn extract(
+        &self,
+        cppn: &mut Network,
+        from: Point,
+        quad: &Quad,
+        outgoing: bool,
+        links: &mut Vec<Link>,
+    ) {
+        for child in quad.children.iter() {
+            if child.variance() > self.variance_threshold {
+                self.extract(cppn, from, child, outgoing, links);
+                continue;
+            }
+
+            let mut difference = |dx: f32, dy: f32| {
+                let point = (child.x + dx * quad.width, child.y + dy * quad.width);
+                (child.weight - self.weight(cppn, from, point, outgoing)).abs()
+            };
+            let horizontal = difference(-1.0, 0.0).min(difference(1.0, 0.0));
+            let vertical = difference(0.0, -1.0).min(difference(0.0, 1.0));
+
+            if horizontal.max(vertical) > self.band_threshold && child.weight != 0.0 {
+                let point = (child.x, child.y);
+                links.push(if outgoing {
+                    (from, point, child.weight)
+                } else {
+                    (point, from, child.weight)
+                });
+            }
+        }
+    }
+
+    /// Connections into (or out of when `outgoing`) the node at `from`
+    fn search(&self, cppn: &mut Network, from: Point, outgoing: bool) -> Vec<Link> {
+        let mut root = Quad {
+            x: 0.0,
+            y: 0.0,
+            width: 1.0,
+            weight: 0.0,
+            children: vec![],
+        };
+        self.divide(cppn, from, &mut root, 0, outgoing);
+
+        let mut links = vec![];
+        self.extract(cppn, from, &root, outgoing, &mut links);
+        links
+    }
+
+    /// Searches the CPPN for hidden nodes and their connections, starting from the inputs and
+    /// repeating from new hidden nodes `iteration_level` times, then connects hidden nodes to
+    /// the outputs. Nodes that aren't on a path from an input to an output are removed
+    ///
+    /// # Arguments
+    ///
+    /// * `cppn` - Genome with 5 inputs and 1 output
+    pub fn network(&self, cppn: &Genome) -> Network {
+        assert_eq!((cppn.inputs, cppn.outputs), (5, 1));
+        let mut query = Network::from_genome(cppn.clone());
+
+        let inputs = self.inputs.len() as u16;
+        let outputs = self.outputs.len() as u16;
+        let mut hidden: Vec<Point> = vec![];
+        let mut ids: HashMap<(u32, u32), u16> = HashMap::new();
+        let mut links: Vec<(u16, u16, f32)> = vec![];
+
+        let mut frontier: Vec<(u16, Point)> = (0..inputs).zip(self.inputs.clone()).collect();
+        for _ in 0..=self.iteration_level {
+            let mut found = vec![];
+            for (start, point) in frontier {
+                for (_, end, weight) in self.search(&mut query, point, true) {
+                    let next = inputs + outputs + ids.len() as u16;
+                    let id = *ids.entry(key(end)).or_insert_with(|| {
+                        hidden.push(end);
+                        found.push((next, end));
+                        next
+                    });
+                    links.push((start, id, weight));
+                }
+            }
+            frontier = found;
+        }
+
+        for (i, point) in self.outputs.iter().enumerate() {
+            for (start, _, weight) in self.search(&mut query, *point, false) {
+                if let Some(id) = ids.get(&key(start)) {
+                    links.push((*id, inputs + i as u16, weight));
+                }
+            }
+        }
+
+        // Keep hidden nodes reachable from the inputs that also lead to an output
+        let reach = |from: Vec<u16>, forward: bool| {
+            let mut seen: HashSet<u16> = from.iter().copied().collect();
+            let mut stack = from;
+            while let Some(node) = stack.pop() {
+                for (start, end, _) in links.iter() {
+                    let (a, b) = if forward { (start, end) } else { (end, start) };
+                    if *a == node && seen.insert(*b) {
+                        stack.push(*b);
+                    }
+                }
+            }
+            seen
+        };
+        let forward = reach((0..inputs).collect(), true);
+        let backward = reach((inputs..inputs + outputs).collect(), false);
+        let keep =
+            |id: &u16| *id < inputs + outputs || (forward.contains(id) && backward.contains(id));
+
+        let mut genome = Genome::new(inputs, outputs);
+        for id in (inputs + outputs..inputs + outputs + hidden.len() as u16).filter(keep) {
+            genome.nodes.insert(id, Neuron::default());
+        }
+
+        for (start, end, weight) in links {
+            if keep(&start) && keep(&end) {
+                let connection = Connection {
+                    weight,
+                    enabled: true,
+                    innovation: None,
+                };
+                genome.connections.entry((start, end)).or_insert(connection);
+            }
+        }
+
+        Network::from_genome(genome)
+    }
+}
+
+/// How `Neat` turns genomes into the networks that run `Task`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) enum Encoding {
+    /// Genomes are the networks
+    Direct,
+    /// Genomes are CPPNs describing the connections of a substrate
+    HyperNeat(Substrate),
+    /// Genomes are CPPNs searched for hidden nodes and connections by ES-HyperNEAT
+    EsHyperNeat(EvolvableSubstrate),
+}
+
+impl Encoding {
+    pub(crate) fn decode(&self, genome: &Genome) -> Network {
+        match self {
+            Encoding::Direct => Network::from_genome(genome.clone()),
+            Encoding::HyperNeat(substrate) => substrate.network(genome),
+            Encoding::EsHyperNeat(substrate) => substrate.network(genome),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activation::Activation;
+    use crate::genome::GenomeBuilder;
+
+    fn identity() -> Neuron {
+        Neuron {
+            activation: 1.0,
+            function: Activation::Identity,
+            ..Neuron::default()
+        }
+    }
+
+    #[test]
+    fn test_substrate() {
+        let substrate = Substrate::new(vec![
+            vec![vec![-1.0, 0.0], vec![1.0, 0.0]],
+            vec![vec![0.0, 1.0]],
+        ]);
+        assert_eq!(substrate.cppn_inputs(), 5);
+
+        // Weight is the x coordinate of the start of the connection
+        let cppn = GenomeBuilder::new(5, 1)
+            .neuron(5, identity())
+            .connection(0, 5, 1.0)
+            .build()
+            .unwrap();
+
+        let mut network = substrate.network(&cppn);
+        network.prop(vec![0.0, 1.0]);
+
+        let weight = (1.0 - 0.2) / (1.0 - 0.2) * 3.0;
+        let expected = 1.0 / (1.0 + (-4.9f32 * weight).exp());
+        assert_eq!(network.get_outputs(), vec![expected]);
+
+        // Weights below the threshold are not expressed
+        let cppn = GenomeBuilder::new(5, 1)
+            .neuron(5, identity())
+            .connection(0, 5, 0.1)
+            .build()
+            .unwrap();
+        assert!(!substrate.network(&cppn).to_json().contains("\"in\""));
+    }
+
+    #[test]
+    fn test_layers() {
+        let substrate = Substrate {
+            leo: true,
+            ..Substrate::new(vec![
+                vec![vec![0.0, 0.0, 0.0]; 3],
+                vec![vec![0.0, 0.0, 1.0]; 4],
+                vec![vec![0.0, 0.0, 2.0]; 2],
+                vec![vec![0.0, 0.0, 3.0]; 2],
+            ])
+        };
+        assert_eq!(substrate.cppn_inputs(), 7);
+        assert_eq!(substrate.cppn_outputs(), 2);
+
+        // Constant input expresses every connection
+        let cppn = GenomeBuilder::new(7, 2)
+            .neuron(7, identity())
+            .neuron(8, identity())
+            .connection(6, 7, 0.5)
+            .connection(6, 8, 1.0)
+            .build()
+            .unwrap();
+
+        let genome = Genome::from_json(&substrate.network(&cppn).to_json()).unwrap();
+        assert_eq!(genome.nodes().count(), 3 + 4 + 2 + 2);
+        assert_eq!(genome.connections().count(), 3 * 4 + 4 * 2 + 2 * 2);
+        assert!(genome.connection(0, 5).is_some());
+        assert!(genome.connection(9, 3).is_some());
+        assert_eq!(genome.connection(0, 5).unwrap().weight, 1.5);
+    }
+
+    #[test]
+    fn test_evolvable() {
+        let substrate = EvolvableSubstrate::new(vec![(-0.5, -1.0)], vec![(0.5, 1.0)]);
+
+        // Constant weight has no information to place nodes at
+        let cppn = GenomeBuilder::new(5, 1)
+            .neuron(5, identity())
+            .connection(4, 5, 1.0)
+            .build()
+            .unwrap();
+        let genome = Genome::from_json(&substrate.network(&cppn).to_json()).unwrap();
+        assert_eq!(genome.nodes().count(), 2);
+        assert_eq!(genome.connections().count(), 0);
+
+        // Band of large weights where x2 = -x1
+        let cppn = GenomeBuilder::new(5, 1)
+            .neuron(
+                5,
+                Neuron {
+                    activation: 1.0,
+                    function: Activation::Gauss,
+                    ..Neuron::default()
+                },
+            )
+            .connection(0, 5, 3.0)
+            .connection(2, 5, 3.0)
+            .build()
+            .unwrap();
+        let mut network = substrate.network(&cppn);
+        let genome = Genome::from_json(&network.to_json()).unwrap();
+        assert!(genome.nodes().count() > 2);
+        assert!(genome.connection(0, 1).is_none());
+
+        // Every hidden node is between the input and the output
+        for (id, _) in genome.nodes().filter(|(id, _)| *id > 1) {
+            assert!(genome.connections().any(|((_, end), _)| end == id));
+            assert!(genome.connections().any(|((start, _), _)| start == id));
+        }
+
+        network.prop(vec![1.0]);
+        assert_eq!(network.get_outputs().len(), 1);
+    }
+}
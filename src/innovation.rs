@@ -26,12 +26,28 @@ impl InnovationCounter {
         self.count
     }
 
-    pub fn get(&self, conn: (u16, u16)) -> Option<u16> {
-        if let Some(innovation) = self.connections.get(&conn) {
-            Some(*innovation)
-        } else {
-            None
+    /// Records a connection with an innovation number from another counter unless it is already
+    /// known, returns the innovation number the connection has in this counter. The connection
+    /// is given a new number when another connection already has `innovation`
+    pub fn register(&mut self, conn: (u16, u16), innovation: u16) -> u16 {
+        if let Some(known) = self.connections.get(&conn) {
+            return *known;
+        }
+        if self.connections.values().any(|i| *i == innovation) {
+            return self.add(conn);
         }
+
+        self.connections.insert(conn, innovation);
+        innovation
+    }
+
+    /// Makes sure new innovation numbers are greater than `last`
+    pub fn reserve(&mut self, last: u16) {
+        self.count = self.count.max(last);
+    }
+
+    pub fn get(&self, conn: (u16, u16)) -> Option<u16> {
+        self.connections.get(&conn).copied()
     }
 }
 
@@ -46,5 +62,14 @@ mod tests {
         assert_eq!(test.add((0, 3)), 4);
         assert_eq!(test.add((2, 3)), 5);
         assert_eq!(test.add((0, 3)), 4);
+
+        assert_eq!(test.register((1, 3), 9), 9);
+        assert_eq!(test.register((0, 3), 9), 4);
+        test.reserve(9);
+        assert_eq!(test.add((1, 4)), 10);
+
+        // Different connection with a number already in use is renumbered
+        assert_eq!(test.register((2, 4), 9), 11);
+        assert_eq!(test.get((1, 3)), Some(9));
     }
 }
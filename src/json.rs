@@ -0,0 +1,281 @@
+use serde::de::Error;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use super::activation::{Activation, Aggregation};
+use super::genome::{Connection, Genome, GenomeError, Neuron};
+
+/// Version of the JSON format written by `to_json`
+pub(crate) const VERSION: u32 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum NodeType {
+    Input,
+    Output,
+    Hidden,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct NodeEntry {
+    pub(crate) id: u16,
+    #[serde(rename = "type")]
+    pub(crate) kind: NodeType,
+    pub(crate) activation: f32,
+    #[serde(default)]
+    pub(crate) bias: f32,
+    #[serde(default)]
+    pub(crate) function: Activation,
+    #[serde(default)]
+    pub(crate) aggregation: Aggregation,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ConnectionEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) innovation: Option<u16>,
+    #[serde(rename = "in")]
+    pub(crate) start: u16,
+    #[serde(rename = "out")]
+    pub(crate) end: u16,
+    pub(crate) weight: f32,
+    #[serde(default = "enabled")]
+    pub(crate) enabled: bool,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+/// Stable JSON layout of a `Genome` or `Network`, independent of how they are stored. The
+/// format is documented on `Network` `to_json`
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Document {
+    pub(crate) version: u32,
+    pub(crate) inputs: u16,
+    pub(crate) outputs: u16,
+    pub(crate) nodes: Vec<NodeEntry>,
+    pub(crate) connections: Vec<ConnectionEntry>,
+}
+
+impl Document {
+    pub(crate) fn new(inputs: u16, outputs: u16) -> Document {
+        Document {
+            version: VERSION,
+            inputs,
+            outputs,
+            nodes: vec![],
+            connections: vec![],
+        }
+    }
+
+    /// Type a node with the given id must have
+    fn kind(&self, id: u16) -> NodeType {
+        if id < self.inputs {
+            NodeType::Input
+        } else if id - self.inputs < self.outputs {
+            NodeType::Output
+        } else {
+            NodeType::Hidden
+        }
+    }
+
+    pub(crate) fn push_node(&mut self, id: u16, neuron: Neuron) {
+        self.nodes.push(NodeEntry {
+            id,
+            kind: self.kind(id),
+            activation: neuron.activation,
+            bias: neuron.bias,
+            function: neuron.function,
+            aggregation: neuron.aggregation,
+        });
+    }
+
+    pub(crate) fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Document should always serialize")
+    }
+
+    pub(crate) fn from_genome(genome: &Genome) -> Document {
+        let mut document = Document::new(genome.inputs, genome.outputs);
+        for (id, neuron) in genome.nodes.iter() {
+            document.push_node(*id, *neuron);
+        }
+
+        document.connections = genome
+            .connections
+            .iter()
+            .map(|(connection, info)| ConnectionEntry {
+                innovation: info.innovation,
+                start: connection.0,
+                end: connection.1,
+                weight: info.weight,
+                enabled: info.enabled,
+            })
+            .collect();
+
+        document
+    }
+
+    /// Checks the document describes a valid genome and builds it
+    pub(crate) fn into_genome(self) -> serde_json::Result<Genome> {
+        if self.version != VERSION {
+            return Err(Error::custom(format!(
+                "unsupported version {}, expected {}",
+                self.version, VERSION
+            )));
+        }
+
+        let mut hidden = 0;
+        let mut seen = HashSet::new();
+        for node in self.nodes.iter() {
+            if !seen.insert(node.id) {
+                return Err(Error::custom(format!("duplicate node {}", node.id)));
+            }
+
+            let expected = self.kind(node.id);
+            if node.kind != expected {
+                return Err(Error::custom(format!(
+                    "node {} has type {:?} but must be {:?}",
+                    node.id, node.kind, expected
+                )));
+            }
+
+            if expected == NodeType::Hidden {
+                hidden += 1;
+            }
+        }
+
+        let fixed = self.inputs as usize + self.outputs as usize;
+        if seen.len() - hidden != fixed {
+            return Err(Error::custom(format!(
+                "expected {} input and {} output nodes",
+                self.inputs, self.outputs
+            )));
+        }
+
+        // Inputs and outputs keep their positions from `Genome::new` as `Network` relies on them
+        let mut genome = Genome::new(self.inputs, self.outputs);
+        for node in self.nodes.iter() {
+            let neuron = Neuron {
+                activation: node.activation,
+                bias: node.bias,
+                function: node.function,
+                aggregation: node.aggregation,
+            };
+
+            if node.kind == NodeType::Hidden {
+                genome.nodes.insert(node.id, neuron);
+            } else {
+                genome.nodes[&node.id] = neuron;
+            }
+        }
+
+        for connection in self.connections.iter() {
+            let key = (connection.start, connection.end);
+            let info = Connection {
+                weight: connection.weight,
+                enabled: connection.enabled,
+                innovation: connection.innovation,
+            };
+
+            if genome.connections.insert(key, info).is_some() {
+                let err = GenomeError::DuplicateConnection(key.0, key.1);
+                return Err(Error::custom(err));
+            }
+        }
+
+        genome.validate().map_err(Error::custom)?;
+        Ok(genome)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::neat::NeatSettings;
+    use crate::network::Network;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    const EXAMPLE: &str = r#"{
+        "version": 1,
+        "inputs": 2,
+        "outputs": 1,
+        "nodes": [
+            { "id": 5, "type": "hidden", "activation": 4.9 },
+            { "id": 2, "type": "output", "activation": 1.0, "bias": -0.5 },
+            { "id": 0, "type": "input", "activation": 4.9 },
+            { "id": 1, "type": "input", "activation": 4.9 }
+        ],
+        "connections": [
+            { "in": 0, "out": 2, "weight": 0.7, "enabled": false },
+            { "in": 0, "out": 5, "weight": 1.0 },
+            { "in": 5, "out": 2, "weight": 2.0 }
+        ]
+    }"#;
+
+    #[test]
+    fn test_import() {
+        let mut network = Network::from_json(EXAMPLE).unwrap();
+        network.prop(vec![0.5, 1.0]);
+
+        let hidden = 1.0 / (1.0 + (-4.9f32 * 0.5).exp());
+        let output = 1.0 / (1.0 + (-(2.0 * hidden - 0.5)).exp());
+        assert_eq!(network.get_outputs(), vec![output]);
+
+        let invalid = [
+            EXAMPLE.replace("\"version\": 1", "\"version\": 2"),
+            EXAMPLE.replace("\"id\": 5", "\"id\": 1"),
+            EXAMPLE.replace(
+                "\"id\": 5, \"type\": \"hidden\"",
+                "\"id\": 5, \"type\": \"input\"",
+            ),
+            EXAMPLE.replace("\"out\": 5", "\"out\": 1"),
+            EXAMPLE.replace("\"out\": 5", "\"out\": 7"),
+            EXAMPLE.replace("\"in\": 0, \"out\": 5", "\"in\": 0, \"out\": 2"),
+            EXAMPLE.replace(
+                "\"id\": 2, \"type\": \"output\"",
+                "\"id\": 3, \"type\": \"output\"",
+            ),
+        ];
+        for json in invalid.iter() {
+            assert!(Network::from_json(json).is_err(), "{}", json);
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut rng = StdRng::seed_from_u64(4);
+
+        for mut genome in fixtures::genomes(3, 2, 5, 20, &NeatSettings::default(), 4) {
+            for (_, node) in genome.nodes.iter_mut() {
+                node.bias = rng.gen_range(-1.0, 1.0);
+                node.function = Activation::Tanh;
+                node.aggregation = Aggregation::Max;
+            }
+
+            let json = genome.to_json();
+            let document: Document = serde_json::from_str(&json).unwrap();
+            assert!(document.connections.iter().all(|c| c.innovation.is_some()));
+            let copy = document.into_genome().unwrap();
+            assert_eq!(
+                copy.nodes.keys().collect::<Vec<_>>(),
+                genome.nodes.keys().collect::<Vec<_>>()
+            );
+            assert_eq!(copy.connections.len(), genome.connections.len());
+
+            let mut network = Network::new(genome).unwrap();
+            let mut imported = Network::from_json(&network.to_json()).unwrap();
+            let mut copy = Network::new(copy).unwrap();
+            for _ in 0..3 {
+                let inputs: Vec<f32> = (0..3).map(|_| rng.gen_range(-1.0, 1.0)).collect();
+                network.prop(inputs.clone());
+                imported.prop(inputs.clone());
+                copy.prop(inputs);
+                assert_eq!(network.get_outputs(), imported.get_outputs());
+                assert_eq!(network.get_outputs(), copy.get_outputs());
+            }
+        }
+    }
+}
@@ -27,12 +27,43 @@
 //! }
 //! ```
 
+mod activation;
+mod codegen;
+#[cfg(feature = "distributed")]
+mod distributed;
+mod evaluator;
+#[cfg(test)]
+mod fixtures;
 mod genome;
+mod hyperneat;
 mod innovation;
+mod json;
+mod map_elites;
 mod neat;
+mod neat_python;
 mod network;
+mod novelty;
+#[cfg(feature = "onnx")]
+mod onnx;
+mod pareto;
+mod random;
+mod subprocess;
+mod visualize;
 
-pub use crate::neat::Neat;
-pub use crate::neat::NeatSettings;
+pub use crate::activation::{Activation, Aggregation};
+#[cfg(feature = "distributed")]
+pub use crate::distributed::{run_worker, Coordinator};
+pub use crate::neat::Aggregate;
+pub use crate::neat::Eviction;
+pub use crate::neat::InitialConnection;
+pub use crate::neat::Phase;
+pub use crate::neat::Selection;
+pub use crate::neat::{FailedGenome, Neat};
+pub use crate::neat::{NeatSettings, PopulationError, SettingsError};
+pub use evaluator::{Evaluation, Evaluator, Failure};
+pub use genome::{Connection, Genome, GenomeBuilder, GenomeError, Neuron};
+pub use hyperneat::{EvolvableSubstrate, Substrate};
+pub use map_elites::{Cells, CellsError, Elite, MapElites, MapElitesError};
 pub use network::Network;
 pub use network::Task;
+pub use subprocess::{External, Subprocess};
@@ -0,0 +1,700 @@
+use rand::rngs::StdRng;
+use rand::seq::IteratorRandom;
+use rand::{Rng, SeedableRng};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+#[cfg(feature = "parallel")]
+use rayon::ThreadPool;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+#[cfg(feature = "parallel")]
+use std::sync::Arc;
+
+use super::evaluator::Evaluation;
+use super::genome::Genome;
+use super::innovation::InnovationCounter;
+#[cfg(feature = "parallel")]
+use super::neat::parallel;
+use super::neat::{evaluate, NeatSettings, SettingsError};
+use super::network::{Network, Task};
+use super::random::Random;
+
+/// Iterations of Lloyd's algorithm used to place CVT centroids
+const LLOYD_ITERATIONS: usize = 20;
+
+/// How behaviour descriptors are divided into the cells of a `MapElites` archive
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Cells {
+    /// Regular grid with `bins[i]` cells between `min[i]` and `max[i]` in each dimension,
+    /// descriptors outside the range go in the cells at the edges
+    Grid {
+        bins: Vec<usize>,
+        min: Vec<f32>,
+        max: Vec<f32>,
+    },
+    /// Cells of the Voronoi tessellation formed by `centroids`, each descriptor goes in the cell
+    /// of its nearest centroid. Scales to many dimensions where a grid has too many cells
+    Centroids(Vec<Vec<f32>>),
+}
+
+/// Problem with `Cells` found by `validate`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CellsError {
+    /// No centroids, or `cvt` was asked for no cells
+    NoCells,
+    /// `bins`, `min` and `max`, or the centroids, have different numbers of dimensions or none
+    Dimensions,
+    /// Dimension of the grid with no bins
+    NoBins(usize),
+    /// Dimension where `max` isn't finite and above `min`
+    Range(usize),
+    /// `cvt` was given fewer samples than cells
+    Samples,
+}
+
+impl fmt::Display for CellsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CellsError::NoCells => write!(f, "there must be at least one cell"),
+            CellsError::Dimensions => {
+                write!(f, "every dimension must be given and there must be one")
+            }
+            CellsError::NoBins(i) => write!(f, "dimension {} has no bins", i),
+            CellsError::Range(i) => write!(f, "dimension {} has max not above min", i),
+            CellsError::Samples => write!(f, "there must be a sample for every cell"),
+        }
+    }
+}
+
+impl Error for CellsError {}
+
+/// Problem found by `MapElites` `new` with the cells or settings it was given
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MapElitesError {
+    /// Cells failed `Cells` `validate`
+    Cells(CellsError),
+    /// Settings failed `NeatSettings` `validate`
+    Settings(SettingsError),
+}
+
+impl fmt::Display for MapElitesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapElitesError::Cells(err) => write!(f, "invalid cells: {}", err),
+            MapElitesError::Settings(err) => write!(f, "invalid settings: {}", err),
+        }
+    }
+}
+
+impl Error for MapElitesError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MapElitesError::Cells(err) => Some(err),
+            MapElitesError::Settings(err) => Some(err),
+        }
+    }
+}
+
+impl From<CellsError> for MapElitesError {
+    fn from(err: CellsError) -> MapElitesError {
+        MapElitesError::Cells(err)
+    }
+}
+
+impl From<SettingsError> for MapElitesError {
+    fn from(err: SettingsError) -> MapElitesError {
+        MapElitesError::Settings(err)
+    }
+}
+
+/// Checks `max` is finite and above `min` in every dimension
+fn ranges(min: &[f32], max: &[f32]) -> Result<(), CellsError> {
+    if min.len() != max.len() {
+        return Err(CellsError::Dimensions);
+    }
+    match min
+        .iter()
+        .zip(max.iter())
+        .position(|(low, high)| !(low.is_finite() && high.is_finite() && low < high))
+    {
+        Some(i) => Err(CellsError::Range(i)),
+        None => Ok(()),
+    }
+}
+
+fn distance(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b.iter()).map(|(x, y)| (x - y).powi(2)).sum()
+}
+
+fn nearest(centroids: &[Vec<f32>], point: &[f32]) -> usize {
+    let distances = centroids.iter().map(|c| distance(c, point));
+    let (index, _) = distances
+        .enumerate()
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .expect("Should have at least one centroid");
+
+    index
+}
+
+impl Cells {
+    /// Centroids for CVT-MAP-Elites, spread evenly over the box between `min` and `max` by
+    /// running k-means on uniformly sampled points
+    ///
+    /// # Arguments
+    ///
+    /// * `count` - Number of cells
+    /// * `min` - Lowest value of each descriptor dimension
+    /// * `max` - Highest value of each descriptor dimension
+    /// * `samples` - Number of points sampled, more gives more even cells
+    /// * `seed` - Seed for sampling the points
+    pub fn cvt(
+        count: usize,
+        min: &[f32],
+        max: &[f32],
+        samples: usize,
+        seed: u64,
+    ) -> Result<Cells, CellsError> {
+        if count == 0 {
+            return Err(CellsError::NoCells);
+        }
+        if samples < count {
+            return Err(CellsError::Samples);
+        }
+        if min.is_empty() {
+            return Err(CellsError::Dimensions);
+        }
+        ranges(min, max)?;
+
+        let mut rng = StdRng::seed_from_u64(seed);
+        let points: Vec<Vec<f32>> = (0..samples)
+            .map(|_| {
+                min.iter()
+                    .zip(max.iter())
+                    .map(|(low, high)| rng.gen_range(*low, *high))
+                    .collect()
+            })
+            .collect();
+
+        let mut centroids = points[..count].to_vec();
+        for _ in 0..LLOYD_ITERATIONS {
+            let mut sums = vec![vec![0.0; min.len()]; count];
+            let mut counts = vec![0; count];
+            for point in points.iter() {
+                let cell = nearest(&centroids, point);
+                counts[cell] += 1;
+                for (sum, x) in sums[cell].iter_mut().zip(point.iter()) {
+                    *sum += x;
+                }
+            }
+
+            for ((centroid, sum), count) in centroids.iter_mut().zip(sums).zip(counts) {
+                if count > 0 {
+                    *centroid = sum.into_iter().map(|x| x / count as f32).collect();
+                }
+            }
+        }
+
+        Ok(Cells::Centroids(centroids))
+    }
+
+    /// Checks there is at least one cell and every dimension has bins and a range, or that
+    /// every centroid has the same dimensions. `MapElites` `new` returns the error
+    pub fn validate(&self) -> Result<(), CellsError> {
+        match self {
+            Cells::Grid { bins, min, max } => {
+                if bins.is_empty() || bins.len() != min.len() {
+                    return Err(CellsError::Dimensions);
+                }
+                if let Some(i) = bins.iter().position(|bins| *bins == 0) {
+                    return Err(CellsError::NoBins(i));
+                }
+                ranges(min, max)
+            }
+            Cells::Centroids(centroids) => {
+                let dimensions = centroids.first().ok_or(CellsError::NoCells)?.len();
+                if dimensions == 0 || centroids.iter().any(|c| c.len() != dimensions) {
+                    return Err(CellsError::Dimensions);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Number of dimensions of a descriptor
+    pub fn dimensions(&self) -> usize {
+        match self {
+            Cells::Grid { bins, .. } => bins.len(),
+            Cells::Centroids(centroids) => centroids[0].len(),
+        }
+    }
+
+    /// Total number of cells
+    pub fn len(&self) -> usize {
+        match self {
+            Cells::Grid { bins, .. } => bins.iter().product(),
+            Cells::Centroids(centroids) => centroids.len(),
+        }
+    }
+
+    /// Whether there are no cells
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Index of the cell containing `descriptor`, grid cells are numbered with the first
+    /// dimension changing slowest
+    pub fn cell(&self, descriptor: &[f32]) -> usize {
+        assert_eq!(descriptor.len(), self.dimensions());
+
+        match self {
+            Cells::Grid { bins, min, max } => {
+                let mut index = 0;
+                for (i, x) in descriptor.iter().enumerate() {
+                    let scaled = (x - min[i]) / (max[i] - min[i]) * bins[i] as f32;
+                    let bin = (scaled.max(0.0) as usize).min(bins[i] - 1);
+                    index = index * bins[i] + bin;
+                }
+                index
+            }
+            Cells::Centroids(centroids) => nearest(centroids, descriptor),
+        }
+    }
+
+    /// Center of the cell with the given index
+    pub fn center(&self, mut cell: usize) -> Vec<f32> {
+        match self {
+            Cells::Grid { bins, min, max } => {
+                let mut center = vec![0.0; bins.len()];
+                for i in (0..bins.len()).rev() {
+                    let bin = cell % bins[i];
+                    cell /= bins[i];
+                    center[i] = min[i] + (bin as f32 + 0.5) * (max[i] - min[i]) / bins[i] as f32;
+                }
+                center
+            }
+            Cells::Centroids(centroids) => centroids[cell].clone(),
+        }
+    }
+}
+
+/// Best genome found for a cell of a `MapElites` archive
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Elite {
+    pub genome: Genome,
+    pub fitness: f32,
+    /// Behaviour descriptor the genome was evaluated with
+    pub descriptor: Vec<f32>,
+}
+
+/// Quality-diversity search with MAP-Elites. Rather than a population the archive keeps the most
+/// fit genome for every cell of the behaviour space, giving a map of diverse solutions. `Task`
+/// `behavior` gives the descriptor of a genome, with several episodes the behaviour of each
+/// episode is averaged. Candidates are mutated copies or crosses of random elites. The archive
+/// can be saved with serde like `Neat`
+#[derive(Serialize, Deserialize)]
+pub struct MapElites<T: Task> {
+    cells: Cells,
+    elites: BTreeMap<usize, Elite>,
+    batch: usize,
+    inputs: u16,
+    outputs: u16,
+    innovations: InnovationCounter,
+    settings: NeatSettings,
+    rng: Random,
+    #[cfg(feature = "parallel")]
+    #[serde(skip)]
+    pool: Option<Arc<ThreadPool>>,
+    phantom: PhantomData<T>,
+}
+
+impl<T: Task + std::marker::Sync> MapElites<T> {
+    /// Create new `MapElites` with an empty archive
+    ///
+    /// # Arguments
+    ///
+    /// * `cells` - How behaviour descriptors are divided into cells
+    /// * `batch` - Number of candidates evaluated per step
+    /// * `inputs` - Number of inputs into Network, should match number of outputs of given `Task`
+    /// * `outputs` - Number of outputs from Network, should match number of inputs of given `Task`
+    /// * `settings` - Mutation and evaluation settings, `NeatSettings` `add_node_rate` and
+    ///   similar are used for mutation and `episodes` and `threads` for evaluation
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// use neat::{Cells, MapElites, NeatSettings};
+    ///
+    /// let cells = Cells::Grid { bins: vec![20, 20], min: vec![0.0; 2], max: vec![1.0; 2] };
+    /// let mut map = MapElites::<TaskImplementation>::new(cells, 100, 4, 2, NeatSettings::default())?;
+    /// ```
+    pub fn new(
+        cells: Cells,
+        batch: usize,
+        inputs: u16,
+        outputs: u16,
+        settings: NeatSettings,
+    ) -> Result<MapElites<T>, MapElitesError> {
+        cells.validate()?;
+        settings.validate()?;
+
+        let hidden = settings.initial_connection.hidden();
+        let rng = Random::new(settings.seed);
+
+        Ok(MapElites {
+            cells,
+            elites: BTreeMap::new(),
+            batch,
+            inputs,
+            outputs,
+            innovations: InnovationCounter::new(inputs + outputs + hidden),
+            settings,
+            rng,
+            #[cfg(feature = "parallel")]
+            pool: None,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Evaluate candidates on the given rayon `ThreadPool` instead of the global pool or the one
+    /// created for `NeatSettings` `threads`
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Thread pool to run `Task` episodes on
+    #[cfg(feature = "parallel")]
+    pub fn set_thread_pool(&mut self, pool: Arc<ThreadPool>) {
+        self.pool = Some(pool);
+    }
+
+    fn candidate(&mut self) -> Genome {
+        let mut genome = if self.elites.is_empty() {
+            let (inputs, outputs) = (self.inputs, self.outputs);
+            Genome::initial(
+                inputs,
+                outputs,
+                &mut self.innovations,
+                &self.settings,
+                &mut self.rng,
+            )
+        } else if self.elites.len() > 1 && self.rng.gen::<f32>() < 0.25 {
+            let parents = self.elites.values().choose_multiple(&mut self.rng, 2);
+            let (better, worse) = if parents[0].fitness >= parents[1].fitness {
+                (parents[0], parents[1])
+            } else {
+                (parents[1], parents[0])
+            };
+            Genome::cross(&better.genome, &worse.genome, &mut self.rng)
+        } else {
+            let elite = self.elites.values().choose(&mut self.rng).unwrap();
+            elite.genome.clone()
+        };
+
+        genome.mutate(&mut self.innovations, &self.settings, &mut self.rng);
+        genome
+    }
+
+    /// Descriptor from the behaviours of every episode
+    fn descriptor(&self, behavior: &[f32]) -> Option<Vec<f32>> {
+        let dimensions = self.cells.dimensions();
+        if behavior.is_empty() || behavior.len() % dimensions != 0 {
+            return None;
+        }
+
+        let episodes = (behavior.len() / dimensions) as f32;
+        let mut descriptor = vec![0.0; dimensions];
+        for episode in behavior.chunks(dimensions) {
+            for (sum, x) in descriptor.iter_mut().zip(episode.iter()) {
+                *sum += x / episodes;
+            }
+        }
+
+        Some(descriptor)
+    }
+
+    /// Runs the episodes of every candidate, on the thread pool when `NeatSettings` `threads`
+    /// allows
+    fn evaluate(&mut self, genomes: &[Genome], seeds: &[u64]) -> Vec<Evaluation> {
+        let settings = &self.settings;
+        let run =
+            |genome: &Genome| evaluate::<T>(Network::from_genome(genome.clone()), seeds, settings);
+
+        #[cfg(feature = "parallel")]
+        {
+            if parallel(&mut self.pool, settings) {
+                return match &self.pool {
+                    Some(pool) => pool.install(|| genomes.par_iter().map(run).collect()),
+                    None => genomes.par_iter().map(run).collect(),
+                };
+            }
+        }
+
+        genomes.iter().map(run).collect()
+    }
+
+    /// Evaluates a batch of candidates and places each in its cell if the cell is empty or the
+    /// candidate is more fit than its elite. Genomes without a behaviour of the right length or
+    /// with a fitness or behaviour that isn't finite are discarded. Returns the number of cells that were filled or improved
+    pub fn step(&mut self) -> usize {
+        let genomes: Vec<Genome> = (0..self.batch).map(|_| self.candidate()).collect();
+        let rng = &mut self.rng;
+        let seeds: Vec<u64> = (0..self.settings.episodes).map(|_| rng.gen()).collect();
+
+        let evaluations = self.evaluate(&genomes, &seeds);
+
+        let mut improved = 0;
+        for (genome, evaluation) in genomes.into_iter().zip(evaluations) {
+            let descriptor = match self.descriptor(&evaluation.behavior) {
+                Some(descriptor) => descriptor,
+                None => continue,
+            };
+            if !evaluation.fitness.is_finite() || !descriptor.iter().all(|x| x.is_finite()) {
+                continue;
+            }
+
+            let cell = self.cells.cell(&descriptor);
+            if let Some(elite) = self.elites.get(&cell) {
+                if elite.fitness >= evaluation.fitness {
+                    continue;
+                }
+            }
+
+            let elite = Elite {
+                genome,
+                fitness: evaluation.fitness,
+                descriptor,
+            };
+            self.elites.insert(cell, elite);
+            improved += 1;
+        }
+
+        improved
+    }
+
+    /// Cells of the archive
+    pub fn cells(&self) -> &Cells {
+        &self.cells
+    }
+
+    /// Elites with the index of their cell, in order of the cells
+    pub fn elites(&self) -> impl Iterator<Item = (usize, &Elite)> {
+        self.elites.iter().map(|(cell, elite)| (*cell, elite))
+    }
+
+    /// Elite of the cell containing `descriptor`
+    pub fn elite(&self, descriptor: &[f32]) -> Option<&Elite> {
+        self.elites.get(&self.cells.cell(descriptor))
+    }
+
+    /// Most fit elite in the archive
+    pub fn best(&self) -> Option<&Elite> {
+        self.elites
+            .values()
+            .max_by(|a, b| a.fitness.total_cmp(&b.fitness))
+    }
+
+    /// Fraction of cells with an elite [0.0 - 1.0]
+    pub fn coverage(&self) -> f32 {
+        self.elites.len() as f32 / self.cells.len() as f32
+    }
+
+    /// CSV of every cell with its center and the fitness of its elite, empty when the cell has
+    /// none. Plot the center columns against fitness for a heatmap of the archive
+    pub fn heatmap(&self) -> String {
+        let dimensions = self.cells.dimensions();
+        let mut header: Vec<String> = (0..dimensions).map(|i| format!("x{}", i)).collect();
+        header.insert(0, "cell".to_string());
+        header.push("fitness".to_string());
+
+        let mut csv = header.join(",");
+        csv.push('\n');
+
+        for cell in 0..self.cells.len() {
+            let mut row = vec![cell.to_string()];
+            row.extend(self.cells.center(cell).iter().map(|x| x.to_string()));
+            row.push(match self.elites.get(&cell) {
+                Some(elite) => elite.fitness.to_string(),
+                None => String::new(),
+            });
+
+            csv.push_str(&row.join(","));
+            csv.push('\n');
+        }
+
+        csv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Moves to the first two network outputs, fitness is the distance from the origin
+    struct Reach {
+        position: Vec<f32>,
+        steps: u8,
+    }
+
+    impl Task for Reach {
+        fn new(_: u64) -> Reach {
+            Reach {
+                position: vec![0.0, 0.0],
+                steps: 0,
+            }
+        }
+
+        fn step(&mut self, inputs: Vec<f32>) -> Vec<f32> {
+            self.position = inputs;
+            self.steps += 1;
+            vec![1.0, self.steps as f32 / 5.0]
+        }
+
+        fn score(&self) -> Option<f32> {
+            if self.steps < 5 {
+                None
+            } else {
+                Some(self.position.iter().map(|x| x.powi(2)).sum::<f32>().sqrt())
+            }
+        }
+
+        fn behavior(&self) -> Option<Vec<f32>> {
+            Some(self.position.clone())
+        }
+    }
+
+    #[test]
+    fn test_cells() {
+        let grid = Cells::Grid {
+            bins: vec![4, 2],
+            min: vec![0.0, 0.0],
+            max: vec![1.0, 1.0],
+        };
+        assert_eq!(grid.len(), 8);
+        assert_eq!(grid.cell(&[0.1, 0.9]), 1);
+        assert_eq!(grid.cell(&[0.6, 0.2]), 4);
+        assert_eq!(grid.cell(&[2.0, -1.0]), 6);
+        assert_eq!(grid.center(4), vec![0.625, 0.25]);
+
+        let cvt = Cells::cvt(5, &[0.0, 0.0], &[1.0, 1.0], 1000, 3).unwrap();
+        assert_eq!((cvt.len(), cvt.dimensions()), (5, 2));
+        for cell in 0..cvt.len() {
+            assert_eq!(cvt.cell(&cvt.center(cell)), cell);
+        }
+        assert!(cvt.cell(&[f32::NAN, 0.5]) < cvt.len());
+    }
+
+    #[test]
+    fn test_invalid_cells() {
+        let grid = |bins: Vec<usize>, max: Vec<f32>| Cells::Grid {
+            bins,
+            min: vec![0.0, 0.0],
+            max,
+        };
+        assert_eq!(grid(vec![4, 2], vec![1.0, 1.0]).validate(), Ok(()));
+        assert_eq!(
+            grid(vec![4, 0], vec![1.0, 1.0]).validate(),
+            Err(CellsError::NoBins(1))
+        );
+        assert_eq!(
+            grid(vec![4], vec![1.0, 1.0]).validate(),
+            Err(CellsError::Dimensions)
+        );
+        assert_eq!(
+            grid(vec![4, 2], vec![1.0]).validate(),
+            Err(CellsError::Dimensions)
+        );
+        assert_eq!(
+            grid(vec![4, 2], vec![0.0, f32::INFINITY]).validate(),
+            Err(CellsError::Range(0))
+        );
+        assert_eq!(
+            Cells::Centroids(vec![]).validate(),
+            Err(CellsError::NoCells)
+        );
+        assert_eq!(
+            Cells::Centroids(vec![vec![0.0], vec![0.0, 1.0]]).validate(),
+            Err(CellsError::Dimensions)
+        );
+
+        let cvt = |count, max: &[f32], samples| Cells::cvt(count, &[0.0, 0.0], max, samples, 0);
+        assert_eq!(cvt(0, &[1.0, 1.0], 10).err(), Some(CellsError::NoCells));
+        assert_eq!(cvt(5, &[1.0, 1.0], 4).err(), Some(CellsError::Samples));
+        assert_eq!(cvt(5, &[1.0, 0.0], 10).err(), Some(CellsError::Range(1)));
+        assert_eq!(cvt(5, &[1.0], 10).err(), Some(CellsError::Dimensions));
+
+        let cells = grid(vec![0, 2], vec![1.0, 1.0]);
+        let map = MapElites::<Reach>::new(cells, 20, 2, 2, NeatSettings::default());
+        assert_eq!(
+            map.err(),
+            Some(MapElitesError::Cells(CellsError::NoBins(0)))
+        );
+        let settings = NeatSettings {
+            episodes: 0,
+            ..NeatSettings::default()
+        };
+        let map = MapElites::<Reach>::new(grid(vec![4, 2], vec![1.0, 1.0]), 20, 2, 2, settings);
+        assert_eq!(
+            map.err(),
+            Some(MapElitesError::Settings(SettingsError::NoEpisodes))
+        );
+    }
+
+    #[test]
+    fn test_map_elites() {
+        let settings = NeatSettings {
+            add_node_rate: 0.3,
+            seed: Some(6),
+            ..NeatSettings::default()
+        };
+        let cells = Cells::Grid {
+            bins: vec![5, 5],
+            min: vec![0.0, 0.0],
+            max: vec![1.0, 1.0],
+        };
+        let mut map = MapElites::<Reach>::new(cells, 20, 2, 2, settings).unwrap();
+
+        let improved: usize = (0..10).map(|_| map.step()).sum();
+        assert!(improved >= map.elites().count());
+        assert!(map.coverage() > 0.0);
+
+        for (cell, elite) in map.elites() {
+            assert_eq!(map.cells().cell(&elite.descriptor), cell);
+            assert!(elite.fitness <= map.best().unwrap().fitness);
+            assert_eq!(map.elite(&elite.descriptor).unwrap().fitness, elite.fitness);
+        }
+
+        let heatmap = map.heatmap();
+        assert_eq!(heatmap.lines().count(), 26);
+        assert!(heatmap.starts_with("cell,x0,x1,fitness\n0,0.1,0.1,"));
+
+        let bytes = bincode::serialize(&map).unwrap();
+        let loaded: MapElites<Reach> = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(loaded.elites().count(), map.elites().count());
+    }
+
+    #[test]
+    fn test_threads() {
+        let run = |threads| {
+            let settings = NeatSettings {
+                seed: Some(2),
+                threads,
+                ..NeatSettings::default()
+            };
+            let cells = Cells::cvt(10, &[-1.0, -1.0], &[1.0, 1.0], 100, 0).unwrap();
+            let mut map = MapElites::<Reach>::new(cells, 10, 2, 2, settings).unwrap();
+            for _ in 0..5 {
+                map.step();
+            }
+            map.elites()
+                .map(|(cell, elite)| (cell, elite.fitness))
+                .collect::<Vec<_>>()
+        };
+
+        let sequential = run(Some(1));
+        assert!(!sequential.is_empty());
+        assert_eq!(run(Some(3)), sequential);
+        assert_eq!(run(None), sequential);
+    }
+}
@@ -1,16 +1,39 @@
 use rand::seq::SliceRandom;
 use rand::Rng;
+#[cfg(feature = "parallel")]
 use rayon::prelude::*;
+#[cfg(feature = "parallel")]
+use rayon::{ThreadPool, ThreadPoolBuilder};
 use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
 use std::marker::PhantomData;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+#[cfg(feature = "parallel")]
+use std::sync::Arc;
+use std::time::Duration;
 
-use super::genome::Genome;
+use super::activation::Activation;
+use super::evaluator::{Evaluation, Evaluator, Failure};
+use super::genome::{Genome, GenomeError};
+use super::hyperneat::{Encoding, EvolvableSubstrate, Substrate};
 use super::innovation::InnovationCounter;
 use super::network::Network;
 use super::network::Task;
+use super::novelty::Archive;
+use super::pareto;
+use super::random::Random;
 
-/// Settings on how `Neat` should operate, important for getting good performance
-#[derive(Serialize, Deserialize)]
+/// Settings on how `Neat` should operate, important for getting good performance. Fields
+/// missing when deserializing from a self-describing format such as JSON take their `default`
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct NeatSettings {
     /// Range for connection weights from -weight to +weight
     pub weight: f32,
@@ -29,23 +52,111 @@ pub struct NeatSettings {
     pub activation_mutate: f32,
     /// Chance node will have its activation mutated
     pub activation_mutate_rate: f32,
-    /// Weight given to different connections when determining if two genomes are of the same
+    /// Activation functions new hidden nodes choose from at random, every node uses sigmoid when
+    /// empty. HyperNEAT CPPNs need several such as sin, gauss and abs
+    pub activation_functions: Vec<Activation>,
+    /// Chance a hidden node will have its activation function replaced by one of
+    /// `activation_functions`
+    pub function_mutate_rate: f32,
+    /// Weight given to excess connections, newer than any in the other genome, when determining
+    /// if two genomes are of the same species
+    pub excess_diff: f32,
+    /// Weight given to disjoint connections when determining if two genomes are of the same
     /// species
     pub connections_diff: f32,
-    /// Weight given to different connection weights when determining if two genomes are of the same
-    /// species
+    /// Weight given to the average difference in weight of matching connections when determining
+    /// if two genomes are of the same species
     pub weight_diff: f32,
-    /// Threshold when determining if two genomes are of the same species
+    /// Weight given to different nodes, their activation, bias and functions, when determining if
+    /// two genomes are of the same species
+    pub node_diff: f32,
+    /// Threshold when determining if two genomes are of the same species. The default of 0.7
+    /// groups about the same share of genome pairs as it did before connection differences were
+    /// divided by genome size and weight differences averaged, so it needs no retuning with the
+    /// default weights
     pub species_threshold: f32,
     /// Sets genomes to be feedforward, (no connections going in reverse of an aleady existing
     /// connection between two nodes)
     pub feedforward: bool,
     /// Whether to recalculate fitness if genome was from a previous generation (useful if task
-    /// has some amount of randomness causing fitness to change)
+    /// has some amount of randomness causing fitness to change). Otherwise genomes that survive
+    /// keep the fitness from the seeds of the generation they were evaluated in
     pub reset_fitness: bool,
+    /// Number of episodes each genome runs `Task` for, must be above zero. Every genome evaluated
+    /// in a generation is given the same set of seeds, set `reset_fitness` for surviving genomes
+    /// to be evaluated on them as well
+    pub episodes: usize,
+    /// How scores from each episode are combined into a single fitness
+    pub aggregate: Aggregate,
+    /// Maximum number of `Task` `step` calls in an episode before it is stopped
+    pub max_steps: Option<u64>,
+    /// Maximum time an episode can run before it is stopped, only checked between `Task` `step`
+    /// calls
+    pub timeout: Option<Duration>,
+    /// Score given to an episode stopped by `max_steps` or `timeout` when `Task` `partial_score`
+    /// returns `None`
+    pub budget_fitness: f32,
+    /// Fitness given to a genome when `Task` panics during one of its episodes
+    pub panic_fitness: f32,
+    /// Directory to write a genome whose `Task` panicked, serialized with bincode to
+    /// `panic-<id>-<seed>.genome` where `id` identifies the genome in `Neat` `failures`. It can be
+    /// replayed by decoding it as `Neat` does, with `Network::new` for direct encoding, and
+    /// running `Network` `run_seeded` with the seed
+    pub panic_dump: Option<PathBuf>,
+    /// Number of threads used to evaluate genomes, must be above zero. `None` uses rayon's global
+    /// thread pool and `Some(1)` evaluates sequentially. Always sequential without the `parallel` feature
+    pub threads: Option<usize>,
+    /// Seed for every random decision made by `Neat`, giving identical results between runs when
+    /// `Task` is deterministic. The random state is saved when `Neat` is serialized so a loaded
+    /// run continues the same way. `None` seeds from entropy
+    pub seed: Option<u64>,
+    /// Connections genomes start with in `Neat` `new`
+    pub initial_connection: InitialConnection,
+    /// What genomes are ranked by when removing the less fit half of each species
+    pub selection: Selection,
+    /// Number of nearest behaviours in the population and archive averaged to find the novelty
+    /// of a genome
+    pub novelty_neighbours: usize,
+    /// Novelty a behaviour needs to be added to the archive, adjusted as the archive grows
+    pub novelty_threshold: f32,
+    /// Most behaviours the novelty archive keeps, `None` keeps every one
+    pub novelty_capacity: Option<usize>,
+    /// Which behaviour is removed when the novelty archive is over `novelty_capacity`
+    pub novelty_eviction: Eviction,
+    /// Fitness subtracted for each hidden node when ranking genomes and choosing the best one
+    pub node_penalty: f32,
+    /// Fitness subtracted for each enabled connection when ranking genomes and choosing the best
+    /// one
+    pub connection_penalty: f32,
+    /// Whether the smaller of two genomes with the same fitness or rank is preferred, by the
+    /// number of hidden nodes and enabled connections
+    pub size_tiebreak: bool,
+    /// Enables phased search when set. Once the mean complexity of the population (hidden nodes
+    /// plus enabled connections) grows this much above where the last simplifying phase ended,
+    /// mutations only remove structure and change weights until it stops falling
+    pub phase_threshold: Option<f32>,
+    /// Generations without a new lowest mean complexity that end a simplifying phase
+    pub phase_plateau: usize,
+    /// Chance an enabled connection will be disabled while simplifying, its gene is kept for
+    /// crossover [0.0 - 1.0]
+    pub remove_connection_rate: f32,
+    /// Chance a hidden node and its connections will be removed while simplifying [0.0 - 1.0]
+    pub remove_node_rate: f32,
+    /// Number of species `species_threshold` is adjusted towards after every generation and
+    /// every `Neat` `replace_worst`, `None` keeps it fixed
+    pub species_target: Option<usize>,
+    /// Amount `species_threshold` is raised or lowered by each adjustment
+    pub species_threshold_step: f32,
+    /// Lowest `species_threshold` can be adjusted to
+    pub species_threshold_min: f32,
+    /// Highest `species_threshold` can be adjusted to
+    pub species_threshold_max: f32,
+    /// Number of `Neat` `tick` calls an organism must live for before `replace_worst` can
+    /// remove it
+    pub rt_minimum_time: u64,
 }
 
-impl NeatSettings {
+impl Default for NeatSettings {
     /// Returns `NeatSettings` with the following settings
     /// `weight`: 1.0,
     /// `weight_mutate`: 2.0,
@@ -55,12 +166,43 @@ impl NeatSettings {
     /// `add_node_rate`: 0.15,
     /// `activation_mutate`: 0.05,
     /// `activation_mutate_rate`: 0.1,
+    /// `activation_functions`: [`Activation::Sigmoid`],
+    /// `function_mutate_rate`: 0.0,
+    /// `excess_diff`: 0.5,
     /// `connections_diff`: 0.5,
     /// `weight_diff`: 0.1,
+    /// `node_diff`: 0.1,
     /// `species_threshold`: 0.7,
     /// `feedforward`: true,
-    /// `reset_fitness`: false
-    pub fn default() -> NeatSettings {
+    /// `reset_fitness`: false,
+    /// `episodes`: 1,
+    /// `aggregate`: `Aggregate::Mean`,
+    /// `max_steps`: None,
+    /// `timeout`: None,
+    /// `budget_fitness`: 0.0,
+    /// `panic_fitness`: f32::MIN,
+    /// `panic_dump`: None,
+    /// `threads`: None,
+    /// `seed`: None,
+    /// `initial_connection`: `InitialConnection::Single`,
+    /// `selection`: `Selection::Fitness`,
+    /// `novelty_neighbours`: 15,
+    /// `novelty_threshold`: 1.0,
+    /// `novelty_capacity`: None,
+    /// `novelty_eviction`: `Eviction::Oldest`,
+    /// `node_penalty`: 0.0,
+    /// `connection_penalty`: 0.0,
+    /// `size_tiebreak`: false,
+    /// `phase_threshold`: None,
+    /// `phase_plateau`: 10,
+    /// `remove_connection_rate`: 0.3,
+    /// `remove_node_rate`: 0.1,
+    /// `species_target`: None,
+    /// `species_threshold_step`: 0.05,
+    /// `species_threshold_min`: 0.1,
+    /// `species_threshold_max`: 10.0,
+    /// `rt_minimum_time`: 20
+    fn default() -> NeatSettings {
         NeatSettings {
             weight: 1.0,
             weight_mutate: 2.0,
@@ -70,28 +212,488 @@ impl NeatSettings {
             add_node_rate: 0.15,
             activation_mutate: 0.05,
             activation_mutate_rate: 0.1,
+            activation_functions: vec![Activation::Sigmoid],
+            function_mutate_rate: 0.0,
+            excess_diff: 0.5,
             connections_diff: 0.5,
             weight_diff: 0.1,
+            node_diff: 0.1,
             species_threshold: 0.7,
             feedforward: true,
             reset_fitness: false,
+            episodes: 1,
+            aggregate: Aggregate::Mean,
+            max_steps: None,
+            timeout: None,
+            budget_fitness: 0.0,
+            panic_fitness: f32::MIN,
+            panic_dump: None,
+            threads: None,
+            seed: None,
+            initial_connection: InitialConnection::Single,
+            selection: Selection::Fitness,
+            novelty_neighbours: 15,
+            novelty_threshold: 1.0,
+            novelty_capacity: None,
+            novelty_eviction: Eviction::Oldest,
+            node_penalty: 0.0,
+            connection_penalty: 0.0,
+            size_tiebreak: false,
+            phase_threshold: None,
+            phase_plateau: 10,
+            remove_connection_rate: 0.3,
+            remove_node_rate: 0.1,
+            species_target: None,
+            species_threshold_step: 0.05,
+            species_threshold_min: 0.1,
+            species_threshold_max: 10.0,
+            rt_minimum_time: 20,
+        }
+    }
+}
+
+/// Topology of the genomes `Neat` `new` starts with, before they are mutated once
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum InitialConnection {
+    /// No connections
+    Unconnected,
+    /// One connection from a random input to a random output
+    Single,
+    /// Every input connected to every hidden node and every hidden node to every output. Inputs
+    /// are connected directly to outputs when there are no hidden nodes or `direct` is set
+    Full { hidden: u16, direct: bool },
+    /// Same as `Full` but each connection is only added with a chance of `fraction` [0.0 - 1.0]
+    Partial {
+        fraction: f32,
+        hidden: u16,
+        direct: bool,
+    },
+}
+
+impl InitialConnection {
+    /// Number of hidden nodes every genome starts with
+    pub(crate) fn hidden(self) -> u16 {
+        match self {
+            InitialConnection::Full { hidden, .. } | InitialConnection::Partial { hidden, .. } => {
+                hidden
+            }
+            _ => 0,
+        }
+    }
+}
+
+/// What `Neat` ranks genomes by within a species. Novelty search needs `Task` `behavior`, it
+/// rewards genomes that behave differently from the population and from novel genomes of
+/// previous generations, which helps on deceptive tasks where fitness leads to local optima.
+/// Multi-objective selection needs `Task` `objectives`
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Selection {
+    /// Fitness alone
+    Fitness,
+    /// `weight * novelty + (1 - weight) * fitness` with both scaled to [0.0 - 1.0] over the
+    /// population, `weight` 1.0 is pure novelty search [0.0 - 1.0]
+    Novelty { weight: f32 },
+    /// NSGA-II ranking of `Task` `objectives` by non-dominated sorting and crowding distance,
+    /// within each species or across the whole population when `global` is set
+    Pareto { global: bool },
+}
+
+/// Behaviour removed from a full novelty archive, see `NeatSettings` `novelty_capacity`
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Eviction {
+    /// The one archived first
+    Oldest,
+    /// A random one
+    Random,
+}
+
+/// Phase of a phased search, see `NeatSettings` `phase_threshold`
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Phase {
+    /// Mutations add nodes and connections
+    Complexify,
+    /// Mutations remove nodes and connections
+    Simplify,
+}
+
+/// Tracks mean complexity to switch between phases
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Phasing {
+    phase: Phase,
+    complexity: f32,
+    floor: f32,
+    lowest: f32,
+    stalled: usize,
+}
+
+impl Phasing {
+    fn new() -> Phasing {
+        Phasing {
+            phase: Phase::Complexify,
+            complexity: 0.0,
+            floor: 0.0,
+            lowest: f32::INFINITY,
+            stalled: 0,
+        }
+    }
+
+    fn update(&mut self, complexity: f32, settings: &NeatSettings) {
+        self.complexity = complexity;
+        let threshold = match settings.phase_threshold {
+            Some(threshold) => threshold,
+            None => return,
+        };
+
+        match self.phase {
+            Phase::Complexify if complexity > self.floor + threshold => {
+                self.phase = Phase::Simplify;
+                self.lowest = complexity;
+                self.stalled = 0;
+            }
+            Phase::Complexify => {}
+            Phase::Simplify if complexity < self.lowest => {
+                self.lowest = complexity;
+                self.stalled = 0;
+            }
+            Phase::Simplify => {
+                self.stalled += 1;
+                if self.stalled >= settings.phase_plateau {
+                    self.phase = Phase::Complexify;
+                    self.floor = self.lowest;
+                }
+            }
+        }
+    }
+}
+
+impl NeatSettings {
+    /// Checks the settings can be used to run `Task`. `Neat` `new` panics when they can't and
+    /// `from_genomes` returns the error
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        if self.episodes == 0 {
+            return Err(SettingsError::NoEpisodes);
+        }
+        if self.threads == Some(0) {
+            return Err(SettingsError::NoThreads);
+        }
+
+        Ok(())
+    }
+}
+
+/// Problem with `NeatSettings` found by `validate`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SettingsError {
+    /// `episodes` is 0
+    NoEpisodes,
+    /// `threads` is `Some(0)`
+    NoThreads,
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SettingsError::NoEpisodes => write!(f, "episodes must be above zero"),
+            SettingsError::NoThreads => write!(f, "threads must be above zero"),
+        }
+    }
+}
+
+impl Error for SettingsError {}
+
+/// Problem found by `Neat` `from_genomes` with the genomes or settings it was given
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PopulationError {
+    /// No genomes were given to start from
+    NoGenomes,
+    /// Genome has a different number of inputs and outputs than the first genome
+    Mismatch(u16, u16),
+    /// Genome failed `Genome` `validate`
+    Genome(GenomeError),
+    /// Settings failed `NeatSettings` `validate`
+    Settings(SettingsError),
+}
+
+impl fmt::Display for PopulationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PopulationError::NoGenomes => write!(f, "no genomes to start from"),
+            PopulationError::Mismatch(inputs, outputs) => write!(
+                f,
+                "genome with {} inputs and {} outputs differs from the first genome",
+                inputs, outputs
+            ),
+            PopulationError::Genome(err) => write!(f, "invalid genome: {}", err),
+            PopulationError::Settings(err) => write!(f, "invalid settings: {}", err),
+        }
+    }
+}
+
+impl Error for PopulationError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PopulationError::Genome(err) => Some(err),
+            PopulationError::Settings(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<GenomeError> for PopulationError {
+    fn from(err: GenomeError) -> PopulationError {
+        PopulationError::Genome(err)
+    }
+}
+
+impl From<SettingsError> for PopulationError {
+    fn from(err: SettingsError) -> PopulationError {
+        PopulationError::Settings(err)
+    }
+}
+
+/// How scores of multiple episodes are combined into one fitness
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Aggregate {
+    /// Average of all scores
+    Mean,
+    /// Lowest score
+    Min,
+    /// Middle score, or average of the two middle scores
+    Median,
+    /// Average of scores after dropping the given fraction [0.0 - 0.5) from both the lowest and
+    /// highest scores
+    TrimmedMean(f32),
+}
+
+impl Aggregate {
+    pub(crate) fn apply(self, mut scores: Vec<f32>) -> f32 {
+        assert!(!scores.is_empty());
+        scores.sort_unstable_by(|a, b| a.total_cmp(b));
+
+        let mean = |s: &[f32]| s.iter().sum::<f32>() / s.len() as f32;
+
+        match self {
+            Aggregate::Mean => mean(&scores),
+            Aggregate::Min => scores[0],
+            Aggregate::Median => {
+                let mid = scores.len() / 2;
+                if scores.len() % 2 == 1 {
+                    scores[mid]
+                } else {
+                    (scores[mid - 1] + scores[mid]) / 2.0
+                }
+            }
+            Aggregate::TrimmedMean(fraction) => {
+                let trim = (scores.len() as f32 * fraction) as usize;
+                if trim * 2 >= scores.len() {
+                    mean(&scores)
+                } else {
+                    mean(&scores[trim..scores.len() - trim])
+                }
+            }
         }
     }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 struct Organism {
+    /// Unique for each genome, identifies it in the distance cache
+    #[serde(skip, default = "next_id")]
+    id: u64,
     genome: Genome,
     fitness: Option<f32>,
+    behavior: Vec<f32>,
+    objectives: Vec<f32>,
+    rank: f32,
+    age: u64,
+    /// Not yet considered for the novelty archive, cleared once it has been ranked
+    candidate: bool,
+    #[serde(skip)]
+    failure: Option<Failure>,
 }
 
 impl Organism {
     pub fn new(genome: Genome) -> Organism {
         Organism {
+            id: next_id(),
             genome,
             fitness: None,
+            behavior: vec![],
+            objectives: vec![],
+            rank: 0.0,
+            age: 0,
+            candidate: true,
+            failure: None,
+        }
+    }
+
+    fn set(&mut self, evaluation: Evaluation) {
+        self.fitness = Some(evaluation.fitness);
+        self.behavior = evaluation.behavior;
+        self.objectives = evaluation.objectives;
+        self.failure = evaluation.failure;
+    }
+
+    /// Fitness after the size penalties of `settings`
+    fn adjusted(&self, settings: &NeatSettings) -> f32 {
+        let (hidden, enabled) = self.genome.size();
+        self.fitness.unwrap()
+            - hidden as f32 * settings.node_penalty
+            - enabled as f32 * settings.connection_penalty
+    }
+
+    /// Orders organisms by `rank` from highest, ties going to the smaller genome when
+    /// `size_tiebreak` is set
+    fn compare(&self, other: &Organism, settings: &NeatSettings) -> Ordering {
+        let order = other.rank.total_cmp(&self.rank);
+        if settings.size_tiebreak {
+            order.then_with(|| self.complexity().cmp(&other.complexity()))
+        } else {
+            order
+        }
+    }
+
+    fn complexity(&self) -> usize {
+        let (hidden, enabled) = self.genome.size();
+        hidden + enabled
+    }
+}
+
+fn next_id() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    NEXT.fetch_add(1, AtomicOrdering::Relaxed)
+}
+
+/// Compatibility distance between two organisms, computed once per pair each generation
+fn distance(
+    cache: &mut HashMap<(u64, u64), f32>,
+    first: &Organism,
+    second: &Organism,
+    settings: &NeatSettings,
+) -> f32 {
+    let key = (first.id.min(second.id), first.id.max(second.id));
+    *cache
+        .entry(key)
+        .or_insert_with(|| first.genome.distance(&second.genome, settings))
+}
+
+/// Builds the thread pool for `NeatSettings` `threads` unless a pool was already given, returns
+/// whether genomes should be evaluated in parallel
+#[cfg(feature = "parallel")]
+pub(crate) fn parallel(pool: &mut Option<Arc<ThreadPool>>, settings: &NeatSettings) -> bool {
+    if let (None, Some(threads)) = (&pool, settings.threads) {
+        if threads > 1 {
+            let built = ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .expect("Failed to build thread pool");
+            *pool = Some(Arc::new(built));
+        }
+    }
+
+    settings.threads != Some(1) || pool.is_some()
+}
+
+/// Runs an episode of `Task` for every seed, isolating panics so a single genome can't bring
+/// down the whole generation
+pub(crate) fn evaluate<T: Task>(
+    mut net: Network,
+    seeds: &[u64],
+    settings: &NeatSettings,
+) -> Evaluation {
+    let mut episodes = vec![];
+
+    for seed in seeds {
+        let result =
+            panic::catch_unwind(AssertUnwindSafe(|| net.run_limited::<T>(*seed, settings)));
+
+        match result {
+            Ok(episode) => {
+                let values = episode.behavior.iter().chain(episode.objectives.iter());
+                if episode.fitness.is_nan() || values.copied().any(f32::is_nan) {
+                    let message = String::from("Task scored NaN");
+                    return Evaluation::failed(settings, *seed, message);
+                }
+                episodes.push(episode);
+            }
+            Err(payload) => {
+                return Evaluation::failed(settings, *seed, panic_message(&*payload));
+            }
         }
     }
+
+    Evaluation::combine(episodes, settings)
+}
+
+fn pareto_rank(organisms: &mut [Organism]) {
+    let objectives: Vec<&[f32]> = organisms.iter().map(|org| &org.objectives[..]).collect();
+    let rank = pareto::rank(&objectives);
+
+    for (org, rank) in organisms.iter_mut().zip(rank) {
+        org.rank = rank;
+    }
+}
+
+/// Weight of each value by its rank, 1.0 for the lowest up to the number of values for the
+/// highest, equal values sharing a weight
+fn rank_weights(values: &[f32]) -> Vec<f32> {
+    values
+        .iter()
+        .map(|value| 1.0 + values.iter().filter(|other| *other < value).count() as f32)
+        .collect()
+}
+
+/// Scales finite values to [0.0 - 1.0], all 0.0 when they are equal. Infinite and NaN values
+/// become 0.0
+fn normalize(values: &[f32]) -> Vec<f32> {
+    let finite = values.iter().copied().filter(|v| v.is_finite());
+    let min = finite.clone().fold(f32::INFINITY, f32::min);
+    let max = finite.fold(f32::NEG_INFINITY, f32::max);
+    if max <= min {
+        return vec![0.0; values.len()];
+    }
+
+    values
+        .iter()
+        .map(|v| {
+            if v.is_finite() {
+                (v - min) / (max - min)
+            } else {
+                0.0
+            }
+        })
+        .collect()
+}
+
+/// Text of a panic caught with `catch_unwind`
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        String::from("Task panicked")
+    }
+}
+
+/// Writes `genome` to `dir` as `panic-<id>-<seed>.genome`, returning the path
+fn dump(genome: &Genome, id: u64, seed: u64, dir: &Path) -> bincode::Result<PathBuf> {
+    let path = dir.join(format!("panic-{}-{}.genome", id, seed));
+    fs::create_dir_all(dir)?;
+    fs::write(&path, bincode::serialize(genome)?)?;
+
+    Ok(path)
+}
+
+/// Genome whose `Task` panicked or whose `Evaluator` failed during a step, see `Neat` `failures`
+#[derive(Clone, Debug)]
+pub struct FailedGenome {
+    /// Identifies the genome within the run, part of the dump file name
+    pub id: u64,
+    pub genome: Genome,
+    pub failure: Failure,
+    /// File the genome was written to when `NeatSettings` `panic_dump` is set
+    pub dump: Option<PathBuf>,
 }
 
 /// Struct to run genetic learning algorithm on provided Task
@@ -103,6 +705,20 @@ pub struct Neat<T: Task> {
     innovations: InnovationCounter,
     settings: NeatSettings,
     best: Organism,
+    seeds: Vec<u64>,
+    rng: Random,
+    #[cfg(feature = "parallel")]
+    #[serde(skip)]
+    pool: Option<Arc<ThreadPool>>,
+    #[serde(skip)]
+    evaluator: Option<Box<dyn Evaluator>>,
+    encoding: Encoding,
+    archive: Archive,
+    phasing: Phasing,
+    #[serde(skip)]
+    distances: HashMap<(u64, u64), f32>,
+    #[serde(skip)]
+    failures: Vec<FailedGenome>,
     phantom: PhantomData<T>,
 }
 
@@ -144,19 +760,242 @@ impl<T: Task + std::marker::Sync> Neat<T> {
     /// let mut neat = Neat::<TaskImplementation>::new::(100, 4, 4);
     /// ```
     pub fn new(size: usize, inputs: u16, outputs: u16, settings: NeatSettings) -> Neat<T> {
-        let mut innovations = InnovationCounter::new(inputs + outputs);
+        if let Err(err) = settings.validate() {
+            panic!("Invalid settings: {}", err);
+        }
+
+        // Ids of the initial hidden nodes come before any innovation number
+        let hidden = settings.initial_connection.hidden();
+        let mut innovations = InnovationCounter::new(inputs + outputs + hidden);
+        let mut rng = Random::new(settings.seed);
 
         let mut population = vec![];
 
         for _ in 0..size {
-            let mut genome = Genome::new(inputs, outputs);
-            genome.add_connection(&mut innovations, &settings);
-            genome.mutate(&mut innovations, &settings);
+            let mut genome =
+                Genome::initial(inputs, outputs, &mut innovations, &settings, &mut rng);
+            genome.mutate(&mut innovations, &settings, &mut rng);
+            population.push(Organism::new(genome));
+        }
+
+        Neat::from_population(size, population, innovations, settings, rng)
+    }
+
+    /// Create new `Neat` using HyperNEAT, genomes are evolved as CPPNs which give the weights of
+    /// the connections between the nodes of `substrate`. CPPN outputs use tanh and new nodes
+    /// choose from `NeatSettings` `activation_functions`, which should include functions such as
+    /// sin, gauss and abs for the CPPN to describe regular patterns. `step` returns the network
+    /// decoded from the best CPPN and `best_genome` the CPPN itself
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - Size of population, number of genomes to test per generation
+    /// * `substrate` - Node positions of the networks that run `Task`
+    /// * `settings` - Settings on how `Neat` should operate
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// use neat::{Activation, Neat, NeatSettings, Substrate};
+    ///
+    /// let settings = NeatSettings {
+    ///     activation_functions: vec![Activation::Sigmoid, Activation::Sin, Activation::Gauss],
+    ///     function_mutate_rate: 0.1,
+    ///     ..NeatSettings::default()
+    /// };
+    /// let mut neat = Neat::<TaskImplementation>::hyperneat(100, substrate, settings);
+    /// ```
+    pub fn hyperneat(size: usize, substrate: Substrate, settings: NeatSettings) -> Neat<T> {
+        let (inputs, outputs) = (substrate.cppn_inputs(), substrate.cppn_outputs());
+        Neat::cppn(
+            size,
+            inputs,
+            outputs,
+            Encoding::HyperNeat(substrate),
+            settings,
+        )
+    }
+
+    /// Create new `Neat` using ES-HyperNEAT, genomes are evolved as CPPNs like `hyperneat` but
+    /// only the input and output positions are given by `substrate`. Hidden nodes and their
+    /// connections are placed where the CPPN weight pattern varies the most, see
+    /// `EvolvableSubstrate` for the settings of the search
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - Size of population, number of genomes to test per generation
+    /// * `substrate` - Input and output positions of the networks that run `Task`
+    /// * `settings` - Settings on how `Neat` should operate
+    pub fn es_hyperneat(
+        size: usize,
+        substrate: EvolvableSubstrate,
+        settings: NeatSettings,
+    ) -> Neat<T> {
+        Neat::cppn(size, 5, 1, Encoding::EsHyperNeat(substrate), settings)
+    }
+
+    /// `Neat` evolving CPPNs with tanh outputs decoded by `encoding`
+    fn cppn(
+        size: usize,
+        inputs: u16,
+        outputs: u16,
+        encoding: Encoding,
+        settings: NeatSettings,
+    ) -> Neat<T> {
+        let mut neat = Neat::new(size, inputs, outputs, settings);
+
+        let cppn_outputs = inputs..inputs + outputs;
+        for org in neat.population.iter_mut().chain(Some(&mut neat.best)) {
+            for id in cppn_outputs.clone() {
+                org.genome.nodes[&id].function = Activation::Tanh;
+            }
+        }
+        neat.encoding = encoding;
+
+        neat
+    }
+
+    /// Create new `Neat` starting from existing genomes, such as the best genomes of a previous
+    /// run or hand designed topologies. The genomes are kept and the rest of the population is
+    /// filled with mutated copies of them in turn. Innovation numbers of their connections are
+    /// registered so they stay compatible with each other and with new connections. Connections
+    /// without one, or whose number is used by a different connection or clashes with a node id
+    /// of any genome, are given a new innovation number. Returns an error when there are no
+    /// genomes, they have different numbers of inputs or outputs, one is invalid or the
+    /// settings are invalid
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - Size of population, number of genomes to test per generation
+    /// * `genomes` - Genomes to start from, all with the same number of inputs and outputs. Only
+    ///   the first `size` are used
+    /// * `settings` - Settings on how `Neat` should operate
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// use neat::{Genome, Neat, NeatSettings};
+    ///
+    /// let genome = Genome::from_json(&fs::read_to_string("champion.json")?)?;
+    /// let mut neat = Neat::<TaskImplementation>::from_genomes(100, vec![genome], NeatSettings::default())?;
+    /// ```
+    pub fn from_genomes(
+        size: usize,
+        genomes: Vec<Genome>,
+        settings: NeatSettings,
+    ) -> Result<Neat<T>, PopulationError> {
+        settings.validate()?;
+        let (inputs, outputs) = match genomes.first() {
+            Some(genome) => (genome.inputs, genome.outputs),
+            None => return Err(PopulationError::NoGenomes),
+        };
+
+        let mut innovations = InnovationCounter::new(inputs + outputs);
+        let mut rng = Random::new(settings.seed);
+
+        // New node ids come from innovation numbers so they must not reuse existing ids
+        for genome in genomes.iter() {
+            if (genome.inputs, genome.outputs) != (inputs, outputs) {
+                return Err(PopulationError::Mismatch(genome.inputs, genome.outputs));
+            }
+            genome.validate()?;
+
+            let last = genome.nodes.keys().chain(
+                genome
+                    .connections
+                    .values()
+                    .filter_map(|c| c.innovation.as_ref()),
+            );
+            innovations.reserve(last.copied().max().unwrap_or(0));
+        }
+
+        // Split nodes take the innovation number of their connection as id, so a number can only
+        // be kept when every genome with a node of that id got it from splitting this connection
+        let clashes = |connection: &(u16, u16), innovation: u16| {
+            innovation < inputs + outputs
+                || genomes.iter().any(|genome| {
+                    let split = genome
+                        .connections
+                        .get(connection)
+                        .and_then(|c| c.innovation);
+                    genome.nodes.contains_key(&innovation) && split != Some(innovation)
+                })
+        };
+        let kept: Vec<Vec<bool>> = genomes
+            .iter()
+            .map(|genome| {
+                genome
+                    .connections
+                    .iter()
+                    .map(|(connection, info)| match info.innovation {
+                        Some(innovation) => !clashes(connection, innovation),
+                        None => false,
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut population: Vec<Organism> = genomes
+            .into_iter()
+            .zip(kept)
+            .take(size)
+            .map(|(mut genome, kept)| {
+                for ((connection, info), kept) in genome.connections.iter_mut().zip(kept) {
+                    let innovation = match info.innovation {
+                        Some(innovation) if kept => innovations.register(*connection, innovation),
+                        _ => innovations.add(*connection),
+                    };
+                    info.innovation = Some(innovation);
+                }
+                Organism::new(genome)
+            })
+            .collect();
+
+        let seeds = population.len();
+        for i in seeds..size {
+            let mut genome = population[i % seeds].genome.clone();
+            genome.mutate(&mut innovations, &settings, &mut rng);
             population.push(Organism::new(genome));
         }
 
+        Ok(Neat::from_population(
+            size,
+            population,
+            innovations,
+            settings,
+            rng,
+        ))
+    }
+
+    /// Create new `Neat` with a population of mutated copies of one genome, see `from_genomes`
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - Size of population, number of genomes to test per generation
+    /// * `genome` - Genome to start from, kept unchanged in the population
+    /// * `settings` - Settings on how `Neat` should operate
+    pub fn from_network_seed(
+        size: usize,
+        genome: Genome,
+        settings: NeatSettings,
+    ) -> Result<Neat<T>, PopulationError> {
+        Neat::from_genomes(size, vec![genome], settings)
+    }
+
+    fn from_population(
+        size: usize,
+        population: Vec<Organism>,
+        innovations: InnovationCounter,
+        settings: NeatSettings,
+        rng: Random,
+    ) -> Neat<T> {
         let mut best = population[0].clone();
         best.fitness = Some(f32::MIN);
+        let archive = Archive::new(
+            settings.novelty_threshold,
+            settings.novelty_capacity,
+            settings.novelty_eviction,
+        );
 
         Neat {
             size,
@@ -165,25 +1004,65 @@ impl<T: Task + std::marker::Sync> Neat<T> {
             innovations,
             settings,
             best,
+            seeds: vec![],
+            rng,
+            #[cfg(feature = "parallel")]
+            pool: None,
+            evaluator: None,
+            encoding: Encoding::Direct,
+            archive,
+            phasing: Phasing::new(),
+            distances: HashMap::new(),
+            failures: vec![],
             phantom: PhantomData,
         }
     }
 
+    /// Evaluate genomes using the given `Evaluator` instead of running `Task` locally
+    ///
+    /// # Arguments
+    ///
+    /// * `evaluator` - Evaluator to find the fitness of each genome
+    pub fn set_evaluator(&mut self, evaluator: Box<dyn Evaluator>) {
+        self.evaluator = Some(evaluator);
+    }
+
+    /// Evaluate genomes on the given rayon `ThreadPool` instead of the global pool or the one
+    /// created for `NeatSettings` `threads`
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - Thread pool to run `Task` episodes on
+    #[cfg(feature = "parallel")]
+    pub fn set_thread_pool(&mut self, pool: Arc<ThreadPool>) {
+        self.pool = Some(pool);
+    }
+
     fn speciate(&mut self) -> Vec<Vec<Organism>> {
         let mut species: Vec<Vec<Organism>> = vec![];
 
         if self.settings.reset_fitness {
-            let fitness = Network::new(self.best.genome.clone()).run::<T>();
-            self.best.fitness = Some(fitness);
+            let network = self.encoding.decode(&self.best.genome);
+            let evaluation = match &mut self.evaluator {
+                Some(evaluator) => evaluator
+                    .evaluations(vec![network], &self.seeds, &self.settings)
+                    .remove(0),
+                None => evaluate::<T>(network, &self.seeds, &self.settings),
+            };
+            self.best.set(evaluation);
         }
 
+        let settings = &self.settings;
+        let distances = &mut self.distances;
         'population: for org in self.population.iter() {
-            if org.fitness.unwrap() > self.best.fitness.unwrap() {
+            let (fitness, best) = (org.adjusted(settings), self.best.adjusted(settings));
+            let smaller = settings.size_tiebreak && org.complexity() < self.best.complexity();
+            if fitness > best || (fitness == best && smaller) {
                 self.best = org.clone();
             }
 
             for group in species.iter_mut() {
-                if Genome::same_species(&group[0].genome, &org.genome, &self.settings) {
+                if distance(distances, &group[0], org, settings) < settings.species_threshold {
                     group.push(org.clone());
                     continue 'population;
                 }
@@ -198,19 +1077,22 @@ impl<T: Task + std::marker::Sync> Neat<T> {
 
     fn kill(&mut self) {
         let mut species = self.speciate();
+        self.adjust_threshold(species.len());
 
         self.population = vec![];
 
         for group in species.iter_mut() {
             if group.len() == 1 {
-                let mut rng = rand::thread_rng();
-                if rng.gen::<f32>() > 0.5 {
+                if self.rng.gen::<f32>() > 0.5 {
                     self.population.append(group)
                 }
                 continue;
             }
 
-            group.sort_unstable_by(|a, b| b.fitness.partial_cmp(&a.fitness).unwrap());
+            if let Selection::Pareto { global: false } = self.settings.selection {
+                pareto_rank(group);
+            }
+            group.sort_unstable_by(|a, b| a.compare(b, &self.settings));
             group.drain(group.len() / 2..);
 
             self.population.append(group);
@@ -218,25 +1100,147 @@ impl<T: Task + std::marker::Sync> Neat<T> {
     }
 
     fn execute(&mut self) {
-        let reset_fitness = self.settings.reset_fitness;
-        self.population
-            .par_iter_mut()
-            .filter(|org| org.fitness.is_none() || reset_fitness)
-            .for_each(|mut org| {
-                let mut net = Network::new(org.genome.clone());
-                org.fitness = Some(net.run::<T>());
+        let rng = &mut self.rng;
+        self.seeds = (0..self.settings.episodes).map(|_| rng.gen()).collect();
+
+        let settings = &self.settings;
+        let seeds = &self.seeds;
+        let encoding = &self.encoding;
+        let pending = |org: &&mut Organism| org.fitness.is_none() || settings.reset_fitness;
+        let run = |org: &mut Organism| {
+            org.set(evaluate::<T>(encoding.decode(&org.genome), seeds, settings));
+        };
+
+        if let Some(evaluator) = &mut self.evaluator {
+            let pending: Vec<&mut Organism> = self.population.iter_mut().filter(pending).collect();
+            let networks = pending
+                .iter()
+                .map(|org| encoding.decode(&org.genome))
+                .collect();
+
+            let evaluations = evaluator.evaluations(networks, seeds, settings);
+            for (org, evaluation) in pending.into_iter().zip(evaluations) {
+                org.set(evaluation);
+            }
+            return;
+        }
+
+        #[cfg(feature = "parallel")]
+        {
+            if parallel(&mut self.pool, settings) {
+                let population = &mut self.population;
+
+                match &self.pool {
+                    Some(pool) => {
+                        pool.install(|| population.par_iter_mut().filter(pending).for_each(run))
+                    }
+                    None => population.par_iter_mut().filter(pending).for_each(run),
+                }
+                return;
+            }
+        }
+
+        self.population.iter_mut().filter(pending).for_each(run);
+    }
+
+    /// Collects the genomes that failed in the last `execute`, writing them to
+    /// `NeatSettings` `panic_dump`
+    fn record_failures(&mut self) {
+        self.failures.clear();
+
+        for org in self.population.iter_mut() {
+            let failure = match org.failure.take() {
+                Some(failure) => failure,
+                None => continue,
+            };
+
+            let dump = self.settings.panic_dump.as_ref().map(|dir| {
+                dump(&org.genome, org.id, failure.seed, dir).map_err(|err| Failure {
+                    seed: failure.seed,
+                    message: format!("{}, failed to write genome: {}", failure.message, err),
+                })
+            });
+            let (dump, failure) = match dump {
+                Some(Ok(path)) => (Some(path), failure),
+                Some(Err(failure)) => (None, failure),
+                None => (None, failure),
+            };
+
+            self.failures.push(FailedGenome {
+                id: org.id,
+                genome: org.genome.clone(),
+                failure,
+                dump,
             });
+        }
+    }
+
+    /// Sets the value each organism is ranked by for `NeatSettings` `selection`, updating the
+    /// novelty archive with the behaviours of organisms ranked for the first time and the phase
+    /// of the search
+    fn rank(&mut self) {
+        let total: usize = self.population.iter().map(|org| org.complexity()).sum();
+        let complexity = total as f32 / self.population.len() as f32;
+        self.phasing.update(complexity, &self.settings);
+
+        let candidates: Vec<bool> = self.population.iter().map(|org| org.candidate).collect();
+        for org in self.population.iter_mut() {
+            org.candidate = false;
+        }
+
+        let weight = match self.settings.selection {
+            Selection::Fitness => {
+                for org in self.population.iter_mut() {
+                    org.rank = org.adjusted(&self.settings);
+                }
+                return;
+            }
+            Selection::Pareto { global } => {
+                if global {
+                    pareto_rank(&mut self.population);
+                }
+                return;
+            }
+            Selection::Novelty { weight } => weight,
+        };
+
+        let behaviors: Vec<&[f32]> = self
+            .population
+            .iter()
+            .map(|org| &org.behavior[..])
+            .collect();
+        let novelty = self.archive.update(
+            &behaviors,
+            &candidates,
+            self.settings.novelty_neighbours,
+            &mut self.rng,
+        );
+        let fitness: Vec<f32> = self
+            .population
+            .iter()
+            .map(|org| org.adjusted(&self.settings))
+            .collect();
+
+        let (novelty, fitness) = (normalize(&novelty), normalize(&fitness));
+        for (i, org) in self.population.iter_mut().enumerate() {
+            org.rank = weight * novelty[i] + (1.0 - weight) * fitness[i];
+        }
     }
 
     fn generate(&mut self) {
-        self.population.shuffle(&mut rand::thread_rng());
+        self.distances.clear();
+        self.population.shuffle(&mut self.rng);
 
         let cross_cap = self.size * 3 / 4;
         let length = self.population.len();
 
         if cross_cap > length {
             for i in 0..cross_cap - length {
-                let new = Genome::cross(&self.population[i].genome, &self.population[i + 1].genome);
+                let new = Genome::cross(
+                    &self.population[i].genome,
+                    &self.population[i + 1].genome,
+                    &mut self.rng,
+                );
                 self.population.push(Organism::new(new));
             }
         }
@@ -245,29 +1249,851 @@ impl<T: Task + std::marker::Sync> Neat<T> {
 
         for i in 0..self.size - length {
             let mut new = self.population[i].genome.clone();
-            new.mutate(&mut self.innovations, &self.settings);
+            self.vary(&mut new);
             self.population.push(Organism::new(new));
         }
     }
 
+    /// Mutates a genome with the operators of the current phase
+    fn vary(&mut self, genome: &mut Genome) {
+        match self.phasing.phase {
+            Phase::Complexify => {
+                genome.mutate(&mut self.innovations, &self.settings, &mut self.rng)
+            }
+            Phase::Simplify => genome.prune(&self.settings, &mut self.rng),
+        }
+    }
+
+    /// Moves `species_threshold` a step towards `species_target` species
+    fn adjust_threshold(&mut self, species: usize) {
+        let target = match self.settings.species_target {
+            Some(target) => target,
+            None => return,
+        };
+
+        let settings = &mut self.settings;
+        let threshold = if species < target {
+            settings.species_threshold - settings.species_threshold_step
+        } else if species > target {
+            settings.species_threshold + settings.species_threshold_step
+        } else {
+            return;
+        };
+
+        settings.species_threshold = threshold.clamp(
+            settings.species_threshold_min,
+            settings.species_threshold_max,
+        );
+    }
+
+    /// Indices of the population grouped into species
+    fn species_indices(&mut self) -> Vec<Vec<usize>> {
+        let mut species: Vec<Vec<usize>> = vec![];
+        let settings = &self.settings;
+
+        'population: for (i, org) in self.population.iter().enumerate() {
+            for group in species.iter_mut() {
+                let first = &self.population[group[0]];
+                let distance = distance(&mut self.distances, first, org, settings);
+                if distance < settings.species_threshold {
+                    group.push(i);
+                    continue 'population;
+                }
+            }
+            species.push(vec![i]);
+        }
+
+        species
+    }
+
+    /// `Network` of the organism in slot `index` of the population, for running agents live
+    /// with `replace_worst`
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Slot of the organism, less than the population size
+    pub fn network(&self, index: usize) -> Network {
+        self.encoding.decode(&self.population[index].genome)
+    }
+
+    /// Sets the fitness of the organism in slot `index`, such as the score of a live agent so far
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Slot of the organism, less than the population size
+    /// * `fitness` - Fitness of the organism
+    pub fn report(&mut self, index: usize, fitness: f32) {
+        let org = &mut self.population[index];
+        org.fitness = Some(fitness);
+
+        let settings = &self.settings;
+        if org.adjusted(settings) > self.best.adjusted(settings) {
+            self.best = org.clone();
+        }
+    }
+
+    /// Advances the age of every organism by one, see `NeatSettings` `rt_minimum_time`
+    pub fn tick(&mut self) {
+        for org in self.population.iter_mut() {
+            org.age += 1;
+        }
+    }
+
+    /// Replaces a single organism for real-time NEAT (rtNEAT), evolving agents while they run
+    /// instead of in generations. The organism with the lowest fitness shared with its species,
+    /// among those with a reported fitness that lived for `NeatSettings` `rt_minimum_time`
+    /// ticks, is removed. Fitness is shifted to start at zero before being shared so negative
+    /// scores keep their order. An offspring of a species chosen by the rank of its average
+    /// fitness, the best of `n` species being `n` times as likely as the worst, takes its slot
+    /// and `species_threshold` is adjusted towards `species_target`. Returns the slot of the new organism, whose agent should be given the
+    /// `Network` from `network`, or `None` if no organism can be replaced yet
+    pub fn replace_worst(&mut self) -> Option<usize> {
+        let species = self.species_indices();
+        self.species_count = species.len();
+        self.adjust_threshold(species.len());
+
+        let settings = &self.settings;
+        let population = &self.population;
+        let eligible = |i: &usize| {
+            population[*i].fitness.is_some() && population[*i].age >= settings.rt_minimum_time
+        };
+        let lowest = population
+            .iter()
+            .filter(|org| org.fitness.is_some())
+            .map(|org| org.adjusted(settings))
+            .fold(f32::INFINITY, f32::min);
+
+        let (worst, _) = species
+            .iter()
+            .flat_map(|group| {
+                let size = group.len() as f32;
+                group
+                    .iter()
+                    .filter(|i| eligible(i))
+                    .map(move |i| (*i, (population[*i].adjusted(settings) - lowest) / size))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))?;
+
+        // Average fitness of each species without the removed organism
+        let parents: Vec<(Vec<usize>, f32)> = species
+            .into_iter()
+            .map(|group| {
+                group
+                    .into_iter()
+                    .filter(|i| *i != worst && population[*i].fitness.is_some())
+                    .collect::<Vec<usize>>()
+            })
+            .filter(|group| !group.is_empty())
+            .map(|group| {
+                let total: f32 = group
+                    .iter()
+                    .map(|i| population[*i].adjusted(settings))
+                    .sum();
+                let average = total / group.len() as f32;
+                (group, average)
+            })
+            .collect();
+        if parents.is_empty() {
+            return None;
+        }
+
+        let averages: Vec<f32> = parents.iter().map(|(_, a)| *a).collect();
+        let weights = rank_weights(&averages);
+        let mut choice = self.rng.gen::<f32>() * weights.iter().sum::<f32>();
+        let mut group = &parents[parents.len() - 1].0;
+        for ((members, _), weight) in parents.iter().zip(weights) {
+            if choice < weight {
+                group = members;
+                break;
+            }
+            choice -= weight;
+        }
+
+        let child = if group.len() > 1 && self.rng.gen::<f32>() < 0.5 {
+            let pair: Vec<&usize> = group.choose_multiple(&mut self.rng, 2).collect();
+            let (a, b) = (&self.population[*pair[0]], &self.population[*pair[1]]);
+            let (better, worse) = if a.adjusted(&self.settings) >= b.adjusted(&self.settings) {
+                (a, b)
+            } else {
+                (b, a)
+            };
+            Genome::cross(&better.genome, &worse.genome, &mut self.rng)
+        } else {
+            let mut genome = self.population[*group.choose(&mut self.rng).unwrap()]
+                .genome
+                .clone();
+            self.vary(&mut genome);
+            genome
+        };
+
+        let removed = self.population[worst].id;
+        self.distances
+            .retain(|(a, b), _| *a != removed && *b != removed);
+        self.population[worst] = Organism::new(child);
+        Some(worst)
+    }
+
     /// Goes through one step of progressing a generation. First it executes the task for the
     /// entire population to find their fitness, removes less fit genomes, and finally generates
     /// new genomes and modifies surviving ones. Returns the `Network` and fitness of most fit
-    /// genome from that step.
+    /// genome from that step. The fitness is the score from `Task` without size penalties.
     pub fn step(&mut self) -> (Network, f32) {
         self.execute();
+        self.record_failures();
+        self.rank();
         self.kill();
         self.generate();
 
         (
-            Network::new(self.best.genome.clone()),
+            self.encoding.decode(&self.best.genome),
             self.best.fitness.unwrap(),
         )
     }
 
+    /// Same as `step` but returns the Pareto front of the generation by `Task` `objectives`,
+    /// every genome that no other genome beats in all objectives, with their objectives. Useful
+    /// with `Selection::Pareto` to choose a trade-off between objectives after training
+    pub fn step_pareto(&mut self) -> Vec<(Network, Vec<f32>)> {
+        self.execute();
+        self.record_failures();
+
+        let objectives: Vec<&[f32]> = self
+            .population
+            .iter()
+            .map(|org| &org.objectives[..])
+            .collect();
+        let front = pareto::fronts(&objectives)
+            .into_iter()
+            .next()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|i| {
+                let org = &self.population[i];
+                (self.encoding.decode(&org.genome), org.objectives.clone())
+            })
+            .collect();
+
+        self.rank();
+        self.kill();
+        self.generate();
+
+        front
+    }
+
+    /// Graphviz DOT of the most fit genome. Unlike `Network` `to_dot` this can include disabled
+    /// connections, drawn dashed
+    ///
+    /// # Arguments
+    ///
+    /// * `show_disabled` - Whether to include disabled connections
+    pub fn best_dot(&self, show_disabled: bool) -> String {
+        self.best.genome.to_dot(show_disabled)
+    }
+
+    /// SVG image of the most fit genome, see `best_dot`
+    ///
+    /// # Arguments
+    ///
+    /// * `show_disabled` - Whether to include disabled connections
+    pub fn best_svg(&self, show_disabled: bool) -> String {
+        self.best.genome.to_svg(show_disabled)
+    }
+
+    /// Most fit genome found so far
+    pub fn best_genome(&self) -> &Genome {
+        &self.best.genome
+    }
+
+    /// JSON of the most fit genome in the format described in `Network` `to_json`. Disabled
+    /// connections are included and each connection has its innovation number
+    pub fn best_json(&self) -> String {
+        self.best.genome.to_json()
+    }
+
+    /// Current phase of the search, always `Phase::Complexify` unless `NeatSettings`
+    /// `phase_threshold` is set
+    pub fn phase(&self) -> Phase {
+        self.phasing.phase
+    }
+
+    /// Mean number of hidden nodes plus enabled connections of the genomes evaluated in the last
+    /// step
+    pub fn complexity(&self) -> f32 {
+        self.phasing.complexity
+    }
+
+    /// Genomes whose `Task` panicked or whose `Evaluator` failed in the last step, with the seed,
+    /// message and the file written to `NeatSettings` `panic_dump`. These are given
+    /// `panic_fitness` instead of stopping training
+    pub fn failures(&self) -> &[FailedGenome] {
+        &self.failures
+    }
+
+    /// Behaviours in the novelty archive, empty unless `NeatSettings` `selection` is
+    /// `Selection::Novelty`
+    pub fn archive(&self) -> &[Vec<f32>] {
+        self.archive.behaviors()
+    }
+
     /// Returns the number of species that existed in the last step. Useful for determining
-    /// what to modify in `NeatSettings`
+    /// what to modify in `NeatSettings`, or set `species_target` to adjust `species_threshold`
+    /// automatically
     pub fn species(&self) -> usize {
         self.species_count
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::genome::{Connection, GenomeBuilder, Neuron};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    struct Panics {
+        seed: u64,
+    }
+
+    impl Task for Panics {
+        fn new(seed: u64) -> Panics {
+            Panics { seed }
+        }
+
+        fn step(&mut self, _: Vec<f32>) -> Vec<f32> {
+            assert_ne!(self.seed % 2, 0, "Bad seed");
+            self.seed = 0;
+            vec![0.0]
+        }
+
+        fn score(&self) -> Option<f32> {
+            if self.seed == 0 {
+                Some(1.0)
+            } else {
+                None
+            }
+        }
+    }
+
+    struct Follow {
+        rng: StdRng,
+        steps: u8,
+        score: f32,
+        target: f32,
+        energy: f32,
+    }
+
+    impl Task for Follow {
+        fn new(seed: u64) -> Follow {
+            Follow {
+                rng: StdRng::seed_from_u64(seed),
+                steps: 0,
+                score: 0.0,
+                target: 0.0,
+                energy: 0.0,
+            }
+        }
+
+        fn step(&mut self, inputs: Vec<f32>) -> Vec<f32> {
+            self.score -= (inputs[0] - self.target).abs();
+            self.energy += inputs[0].abs();
+            self.target = self.rng.gen();
+            self.steps += 1;
+            vec![self.target, 1.0]
+        }
+
+        fn score(&self) -> Option<f32> {
+            if self.steps < 10 {
+                None
+            } else {
+                Some(self.score)
+            }
+        }
+
+        fn behavior(&self) -> Option<Vec<f32>> {
+            Some(vec![self.score])
+        }
+
+        // Tracking the target against keeping outputs low
+        fn objectives(&self) -> Option<Vec<f32>> {
+            Some(vec![self.score, -self.energy])
+        }
+    }
+
+    fn train(threads: Option<usize>) -> Vec<(f32, usize)> {
+        let settings = NeatSettings {
+            episodes: 3,
+            threads,
+            seed: Some(7),
+            ..NeatSettings::default()
+        };
+        let mut neat = Neat::<Follow>::new(50, 2, 1, settings);
+
+        (0..5).map(|_| (neat.step().1, neat.species())).collect()
+    }
+
+    #[test]
+    fn test_seeded() {
+        let sequential = train(Some(1));
+
+        assert_eq!(sequential, train(Some(1)));
+        assert_eq!(sequential, train(Some(3)));
+        assert_eq!(sequential, train(None));
+
+        // Saving and loading part way through doesn't change the run
+        let settings = NeatSettings {
+            episodes: 3,
+            seed: Some(7),
+            ..NeatSettings::default()
+        };
+        let mut neat = Neat::<Follow>::new(50, 2, 1, settings);
+        let mut resumed: Vec<(f32, usize)> =
+            (0..2).map(|_| (neat.step().1, neat.species())).collect();
+        let mut neat: Neat<Follow> =
+            bincode::deserialize(&bincode::serialize(&neat).unwrap()).unwrap();
+        resumed.extend((0..3).map(|_| (neat.step().1, neat.species())));
+        assert_eq!(sequential, resumed);
+    }
+
+    #[test]
+    fn test_panic_isolation() {
+        let dir = std::env::temp_dir().join(format!("neat-panic-{}", std::process::id()));
+        let settings = NeatSettings {
+            panic_fitness: -5.0,
+            panic_dump: Some(dir.clone()),
+            ..NeatSettings::default()
+        };
+        let genome = Genome::new(1, 1);
+
+        let network = || Network::new(genome.clone()).unwrap();
+
+        assert_eq!(
+            evaluate::<Panics>(network(), &[1, 3], &settings).fitness,
+            1.0
+        );
+        let evaluation = evaluate::<Panics>(network(), &[1, 2], &settings);
+        assert_eq!(evaluation.fitness, -5.0);
+        assert_eq!(evaluation.failure.unwrap().seed, 2);
+
+        // Every genome panics on even seeds, each is written to its own file
+        let settings = NeatSettings {
+            seed: Some(1),
+            ..settings
+        };
+        let mut neat = Neat::<Panics>::new(20, 1, 1, settings);
+        while neat.failures().is_empty() {
+            neat.step();
+        }
+
+        let failures = neat.failures();
+        assert_eq!(failures.len(), 20);
+        for failed in failures {
+            assert_eq!(failed.failure.seed % 2, 0);
+            assert!(failed.failure.message.contains("Bad seed"));
+            let bytes = fs::read(failed.dump.as_ref().unwrap()).unwrap();
+            let genome: Genome = bincode::deserialize(&bytes).unwrap();
+            assert_eq!(
+                genome.connections().count(),
+                failed.genome.connections().count()
+            );
+        }
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 20);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_novelty() {
+        let settings = NeatSettings {
+            selection: Selection::Novelty { weight: 0.5 },
+            novelty_threshold: 0.5,
+            episodes: 2,
+            seed: Some(3),
+            ..NeatSettings::default()
+        };
+        let mut neat = Neat::<Follow>::new(30, 2, 1, settings);
+
+        for _ in 0..5 {
+            neat.step();
+        }
+        assert!(!neat.archive().is_empty());
+        assert!(neat.archive().iter().all(|behavior| behavior.len() == 2));
+        // Survivors were already considered for the archive, only offspring are candidates
+        assert!(neat
+            .population
+            .iter()
+            .all(|org| org.candidate == org.fitness.is_none()));
+
+        let settings = NeatSettings {
+            novelty_capacity: Some(3),
+            novelty_eviction: Eviction::Random,
+            ..neat.settings.clone()
+        };
+        let mut neat = Neat::<Follow>::new(30, 2, 1, settings);
+        for _ in 0..5 {
+            neat.step();
+            assert!(neat.archive().len() <= 3);
+        }
+
+        assert_eq!(normalize(&[2.0, 4.0, 3.0]), vec![0.0, 1.0, 0.5]);
+        assert_eq!(normalize(&[1.0, 1.0]), vec![0.0, 0.0]);
+        assert_eq!(
+            normalize(&[1.0, f32::INFINITY, 3.0, f32::NAN]),
+            vec![0.0, 0.0, 1.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn test_pareto() {
+        for global in [false, true] {
+            let settings = NeatSettings {
+                selection: Selection::Pareto { global },
+                episodes: 2,
+                seed: Some(4),
+                ..NeatSettings::default()
+            };
+            let mut neat = Neat::<Follow>::new(30, 2, 1, settings);
+
+            for _ in 0..5 {
+                let front = neat.step_pareto();
+                assert!(!front.is_empty());
+
+                let objectives: Vec<&[f32]> = front.iter().map(|(_, o)| &o[..]).collect();
+                assert!(objectives.iter().all(|o| o.len() == 2));
+                assert_eq!(pareto::fronts(&objectives).len(), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parsimony() {
+        let complexity = |penalty: f32| {
+            let settings = NeatSettings {
+                node_penalty: penalty,
+                connection_penalty: penalty,
+                add_node_rate: 0.5,
+                add_connection_rate: 0.5,
+                seed: Some(9),
+                ..NeatSettings::default()
+            };
+            let mut neat = Neat::<Follow>::new(40, 2, 1, settings);
+            for _ in 0..10 {
+                neat.step();
+            }
+
+            neat.best.complexity()
+        };
+        assert!(complexity(10.0) < complexity(0.0));
+
+        // Smaller genome wins a tie
+        let settings = NeatSettings {
+            size_tiebreak: true,
+            ..NeatSettings::default()
+        };
+        let small = Organism::new(Genome::new(2, 1));
+        let mut large = small.clone();
+        large.genome.nodes.insert(3, Neuron::default());
+        assert_eq!(small.compare(&large, &settings), Ordering::Less);
+        assert_eq!(
+            small.compare(&large, &NeatSettings::default()),
+            Ordering::Equal
+        );
+    }
+
+    #[test]
+    fn test_phases() {
+        let settings = NeatSettings {
+            phase_threshold: Some(4.0),
+            phase_plateau: 3,
+            add_connection_rate: 0.8,
+            add_node_rate: 0.5,
+            seed: Some(2),
+            ..NeatSettings::default()
+        };
+        let mut neat = Neat::<Follow>::new(30, 2, 1, settings);
+
+        let mut phases = vec![];
+        for _ in 0..40 {
+            neat.step();
+            phases.push((neat.phase(), neat.complexity()));
+        }
+
+        let start = phases
+            .iter()
+            .position(|(phase, _)| *phase == Phase::Simplify)
+            .unwrap();
+        assert!(phases[start].1 > 4.0);
+
+        let end = start
+            + phases[start..]
+                .iter()
+                .position(|(phase, _)| *phase == Phase::Complexify)
+                .unwrap();
+        let lowest = phases[start..end]
+            .iter()
+            .map(|(_, complexity)| *complexity)
+            .fold(f32::INFINITY, f32::min);
+        assert!(lowest < phases[start].1);
+
+        // Next phase starts relative to where simplifying ended
+        let next = phases[end..]
+            .iter()
+            .find(|(phase, _)| *phase == Phase::Simplify)
+            .unwrap();
+        assert!(next.1 > lowest + 4.0);
+    }
+
+    #[test]
+    fn test_real_time() {
+        let settings = NeatSettings {
+            rt_minimum_time: 3,
+            species_target: Some(4),
+            species_threshold: 0.1,
+            seed: Some(8),
+            ..NeatSettings::default()
+        };
+        let mut neat = Neat::<Follow>::new(20, 2, 1, settings);
+        let run = |neat: &mut Neat<Follow>, index: usize| {
+            let fitness = evaluate::<Follow>(neat.network(index), &[index as u64], &neat.settings);
+            neat.report(index, fitness.fitness);
+        };
+
+        for index in 0..20 {
+            run(&mut neat, index);
+        }
+        assert_eq!(neat.replace_worst(), None);
+
+        for _ in 0..3 {
+            neat.tick();
+        }
+        let mut replaced = vec![];
+        let mut thresholds = vec![];
+        for _ in 0..30 {
+            let index = neat.replace_worst().unwrap();
+            thresholds.push(neat.settings.species_threshold);
+            assert_eq!(neat.population[index].fitness, None);
+            assert_eq!(neat.population[index].age, 0);
+            replaced.push(index);
+
+            run(&mut neat, index);
+            neat.tick();
+        }
+
+        // Only organisms that lived long enough are replaced
+        assert!(replaced.windows(2).all(|pair| pair[0] != pair[1]));
+        assert_eq!(neat.population.len(), 20);
+        assert!(thresholds.iter().any(|threshold| *threshold != 0.1));
+        assert!(neat.best.fitness.unwrap() > f32::MIN);
+
+        assert_eq!(
+            rank_weights(&[-3.0, 5.0, 1.0, 1.0]),
+            vec![1.0, 4.0, 2.0, 2.0]
+        );
+    }
+
+    #[test]
+    fn test_species_target() {
+        let train = |threshold: f32| {
+            let settings = NeatSettings {
+                species_threshold: threshold,
+                species_target: Some(6),
+                species_threshold_step: 0.1,
+                add_node_rate: 0.3,
+                seed: Some(11),
+                ..NeatSettings::default()
+            };
+            let mut neat = Neat::<Follow>::new(50, 2, 1, settings);
+            let thresholds: Vec<f32> = (0..30)
+                .map(|_| {
+                    neat.step();
+                    neat.settings.species_threshold
+                })
+                .collect();
+
+            (neat.species(), thresholds)
+        };
+
+        // Threshold moves from either side towards giving the target number of species
+        let (low, from_low) = train(0.1);
+        let (high, from_high) = train(2.0);
+        assert!(from_low[0] > 0.1 && from_high[0] < 2.0);
+        assert!((from_low[29] - from_high[29]).abs() < 0.3);
+        assert!((2..=10).contains(&low) && (2..=10).contains(&high));
+
+        let settings = NeatSettings {
+            species_target: Some(6),
+            species_threshold: 9.98,
+            ..NeatSettings::default()
+        };
+        let mut neat = Neat::<Follow>::new(10, 2, 1, settings);
+        neat.adjust_threshold(10);
+        assert_eq!(neat.settings.species_threshold, 10.0);
+        neat.adjust_threshold(6);
+        assert_eq!(neat.settings.species_threshold, 10.0);
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let scores = vec![4.0, 1.0, 10.0, 3.0, 2.0];
+
+        assert_eq!(Aggregate::Mean.apply(scores.clone()), 4.0);
+        assert_eq!(Aggregate::Min.apply(scores.clone()), 1.0);
+        assert_eq!(Aggregate::Median.apply(scores.clone()), 3.0);
+        assert_eq!(Aggregate::TrimmedMean(0.2).apply(scores), 3.0);
+
+        assert_eq!(Aggregate::Median.apply(vec![1.0, 4.0, 2.0, 3.0]), 2.5);
+        assert_eq!(Aggregate::TrimmedMean(0.5).apply(vec![1.0, 3.0]), 2.0);
+        assert_eq!(Aggregate::Median.apply(vec![2.0, f32::NAN, 1.0]), 2.0);
+
+        let settings = NeatSettings {
+            episodes: 0,
+            ..NeatSettings::default()
+        };
+        assert_eq!(settings.validate(), Err(SettingsError::NoEpisodes));
+        let settings = NeatSettings {
+            threads: Some(0),
+            ..NeatSettings::default()
+        };
+        assert_eq!(settings.validate(), Err(SettingsError::NoThreads));
+
+        // Settings saved before a field was added still load
+        let settings: NeatSettings = serde_json::from_str(r#"{"episodes": 3}"#).unwrap();
+        assert_eq!(settings.episodes, 3);
+        assert_eq!(
+            settings.rt_minimum_time,
+            NeatSettings::default().rt_minimum_time
+        );
+    }
+
+    #[test]
+    fn test_from_genomes() {
+        let settings = NeatSettings {
+            seed: Some(3),
+            ..NeatSettings::default()
+        };
+        let seed = GenomeBuilder::new(2, 1)
+            .neuron(40, Neuron::default())
+            .connection(0, 40, 1.0)
+            .connection(40, 2, -1.0)
+            .disabled_connection(1, 2, 0.5)
+            .build()
+            .unwrap();
+        let mut previous = Neat::<Follow>::new(10, 2, 1, settings.clone());
+        previous.step();
+        let champion = previous.best_genome().clone();
+
+        let mut neat =
+            Neat::<Follow>::from_genomes(20, vec![seed.clone(), champion], settings.clone())
+                .unwrap();
+        assert_eq!(neat.population.len(), 20);
+        assert_eq!(
+            neat.population[0].genome.connections().count(),
+            seed.connections().count()
+        );
+
+        for organism in neat.population.iter() {
+            for ((start, end), connection) in organism.genome.connections() {
+                assert!(connection.innovation().is_some());
+                assert_eq!(connection.innovation(), neat.innovations.get((start, end)));
+            }
+        }
+
+        for _ in 0..3 {
+            neat.step();
+        }
+        for organism in neat.population.iter() {
+            organism.genome.validate().unwrap();
+        }
+
+        // Two different connections with innovation 5, the first split into node 5
+        let connection = |innovation| Connection {
+            weight: 1.0,
+            enabled: true,
+            innovation: Some(innovation),
+        };
+        let mut first = Genome::new(2, 1);
+        first.connections.insert((0, 2), connection(5));
+        first.connections.insert((0, 5), connection(6));
+        first.connections.insert((5, 2), connection(7));
+        first.nodes.insert(5, Neuron::default());
+        let mut second = Genome::new(2, 1);
+        second.connections.insert((1, 2), connection(5));
+
+        let neat = Neat::<Follow>::from_genomes(2, vec![first, second], settings.clone()).unwrap();
+        assert_eq!(neat.innovations.get((0, 2)), Some(5));
+        assert!(neat.innovations.get((1, 2)).unwrap() > 7);
+
+        let result = Neat::<Follow>::from_genomes(
+            2,
+            vec![seed.clone(), Genome::new(3, 1)],
+            settings.clone(),
+        );
+        assert_eq!(result.err(), Some(PopulationError::Mismatch(3, 1)));
+        let result = Neat::<Follow>::from_genomes(2, vec![], settings.clone());
+        assert_eq!(result.err(), Some(PopulationError::NoGenomes));
+        let mut invalid = seed.clone();
+        invalid.nodes.swap_indices(0, 1);
+        let result = Neat::<Follow>::from_genomes(2, vec![invalid], settings.clone());
+        assert_eq!(
+            result.err(),
+            Some(PopulationError::Genome(GenomeError::MisplacedNode(0)))
+        );
+        let settings = NeatSettings {
+            episodes: 0,
+            ..settings
+        };
+        let result = Neat::<Follow>::from_network_seed(2, seed, settings);
+        assert_eq!(
+            result.err(),
+            Some(PopulationError::Settings(SettingsError::NoEpisodes))
+        );
+    }
+
+    #[test]
+    fn test_hyperneat() {
+        let settings = NeatSettings {
+            activation_functions: vec![Activation::Sigmoid, Activation::Sin, Activation::Gauss],
+            function_mutate_rate: 0.2,
+            add_node_rate: 0.5,
+            seed: Some(5),
+            ..NeatSettings::default()
+        };
+        let substrate = Substrate::new(vec![
+            vec![vec![-1.0, -1.0], vec![1.0, -1.0]],
+            vec![vec![0.0, 0.0]; 3],
+            vec![vec![0.0, 1.0]],
+        ]);
+        let mut neat = Neat::<Follow>::hyperneat(30, substrate, settings);
+
+        for _ in 0..5 {
+            let (mut network, _) = neat.step();
+            network.prop(vec![0.5, 1.0]);
+            assert_eq!(network.get_outputs().len(), 1);
+        }
+
+        let cppn = neat.best_genome();
+        assert_eq!((cppn.inputs(), cppn.outputs()), (5, 1));
+        assert_eq!(cppn.node(5).unwrap().function, Activation::Tanh);
+        assert!(neat
+            .population
+            .iter()
+            .all(|org| org.genome.node(5).unwrap().function == Activation::Tanh));
+    }
+
+    #[test]
+    fn test_es_hyperneat() {
+        let settings = NeatSettings {
+            activation_functions: vec![Activation::Sigmoid, Activation::Sin, Activation::Gauss],
+            function_mutate_rate: 0.2,
+            seed: Some(5),
+            ..NeatSettings::default()
+        };
+        let substrate = EvolvableSubstrate::new(vec![(-1.0, -1.0), (1.0, -1.0)], vec![(0.0, 1.0)]);
+        let mut neat = Neat::<Follow>::es_hyperneat(10, substrate, settings);
+
+        for _ in 0..2 {
+            let (mut network, _) = neat.step();
+            network.prop(vec![0.5, 1.0]);
+            assert_eq!(network.get_outputs().len(), 1);
+        }
+        assert_eq!(neat.best_genome().inputs(), 5);
+    }
+}
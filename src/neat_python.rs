@@ -0,0 +1,186 @@
+use serde::de::Error;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use super::activation::{Activation, Aggregation};
+use super::genome::{Genome, Neuron};
+use super::json::{ConnectionEntry, Document};
+
+#[derive(Deserialize)]
+struct NodeGene {
+    key: i64,
+    bias: f32,
+    response: f32,
+    activation: String,
+    aggregation: String,
+}
+
+#[derive(Deserialize)]
+struct ConnectionGene {
+    key: (i64, i64),
+    weight: f32,
+    enabled: bool,
+}
+
+#[derive(Deserialize)]
+struct Dump {
+    num_inputs: u16,
+    num_outputs: u16,
+    nodes: Vec<NodeGene>,
+    connections: Vec<ConnectionGene>,
+}
+
+/// Equivalent activation and the factor neat-python multiplies its input by
+fn activation(name: &str) -> Option<(Activation, f32)> {
+    let function = match name {
+        "sigmoid" => (Activation::Sigmoid, 5.0),
+        "tanh" => (Activation::Tanh, 2.5),
+        "sin" => (Activation::Sin, 5.0),
+        "gauss" => (Activation::Gauss, 5.0f32.sqrt()),
+        "relu" => (Activation::Relu, 1.0),
+        "identity" => (Activation::Identity, 1.0),
+        "clamped" => (Activation::Clamped, 1.0),
+        "abs" => (Activation::Abs, 1.0),
+        "square" => (Activation::Square, 1.0),
+        _ => return None,
+    };
+
+    Some(function)
+}
+
+fn aggregation(name: &str) -> Option<Aggregation> {
+    let aggregation = match name {
+        "sum" => Aggregation::Sum,
+        "product" => Aggregation::Product,
+        "max" => Aggregation::Max,
+        "min" => Aggregation::Min,
+        "mean" => Aggregation::Mean,
+        "maxabs" => Aggregation::MaxAbs,
+        _ => return None,
+    };
+
+    Some(aggregation)
+}
+
+/// Reads a neat-python genome dumped as JSON, see `Network` `from_neat_python` for the format.
+/// Inputs `-1, -2, ..` become `0, 1, ..`, outputs `0, 1, ..` follow them and hidden nodes are
+/// numbered after the outputs in the order of their keys
+pub(crate) fn genome(json: &str) -> serde_json::Result<Genome> {
+    let dump: Dump = serde_json::from_str(json)?;
+    let inputs = dump.num_inputs as i64;
+    let outputs = dump.num_outputs as i64;
+
+    let mut hidden: Vec<i64> = dump
+        .nodes
+        .iter()
+        .map(|node| node.key)
+        .filter(|key| *key >= outputs)
+        .collect();
+    hidden.sort_unstable();
+
+    let mut ids: HashMap<i64, u16> = HashMap::new();
+    for key in 1..=inputs {
+        ids.insert(-key, (key - 1) as u16);
+    }
+    for key in 0..outputs {
+        ids.insert(key, (inputs + key) as u16);
+    }
+    for (i, key) in hidden.into_iter().enumerate() {
+        ids.insert(key, (inputs + outputs) as u16 + i as u16);
+    }
+
+    let id = |key: i64| {
+        ids.get(&key)
+            .copied()
+            .ok_or_else(|| Error::custom(format!("unknown node {}", key)))
+    };
+
+    let mut document = Document::new(dump.num_inputs, dump.num_outputs);
+    for i in 0..dump.num_inputs {
+        document.push_node(i, Neuron::default());
+    }
+
+    for node in dump.nodes.iter() {
+        if node.key < 0 {
+            return Err(Error::custom(format!("node {} is an input", node.key)));
+        }
+
+        let (function, scale) = activation(&node.activation)
+            .ok_or_else(|| Error::custom(format!("unsupported activation {}", node.activation)))?;
+        let aggregation = aggregation(&node.aggregation).ok_or_else(|| {
+            Error::custom(format!("unsupported aggregation {}", node.aggregation))
+        })?;
+
+        let neuron = Neuron {
+            activation: scale * node.response,
+            bias: scale * node.bias,
+            function,
+            aggregation,
+        };
+        document.push_node(id(node.key)?, neuron);
+    }
+
+    for connection in dump.connections.iter() {
+        let (start, end) = connection.key;
+        document.connections.push(ConnectionEntry {
+            innovation: None,
+            start: id(start)?,
+            end: id(end)?,
+            weight: connection.weight,
+            enabled: connection.enabled,
+        });
+    }
+
+    document.into_genome()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::network::Network;
+
+    const DUMP: &str = r#"{
+        "num_inputs": 2,
+        "num_outputs": 2,
+        "nodes": [
+            { "key": 0, "bias": 0.5, "response": 1.0, "activation": "sigmoid", "aggregation": "sum" },
+            { "key": 1, "bias": 0.0, "response": 2.0, "activation": "relu", "aggregation": "max" },
+            { "key": 14, "bias": -0.2, "response": 0.8, "activation": "tanh", "aggregation": "sum" }
+        ],
+        "connections": [
+            { "key": [-1, 14], "weight": 1.5, "enabled": true },
+            { "key": [-2, 14], "weight": -0.5, "enabled": true },
+            { "key": [14, 0], "weight": 2.0, "enabled": true },
+            { "key": [-1, 0], "weight": 3.0, "enabled": false },
+            { "key": [-1, 1], "weight": 1.0, "enabled": true },
+            { "key": [-2, 1], "weight": 0.5, "enabled": true }
+        ]
+    }"#;
+
+    #[test]
+    fn test_neat_python() {
+        let mut network = Network::from_neat_python(DUMP).unwrap();
+        let (a, b) = (0.3f32, -0.8f32);
+        network.prop(vec![a, b]);
+
+        // Same formulas as neat-python activations
+        let hidden = (2.5 * (-0.2 + 0.8 * (1.5 * a - 0.5 * b))).tanh();
+        let first = 1.0 / (1.0 + (-5.0 * (0.5 + 2.0 * hidden)).exp());
+        let second = (2.0 * a.max(0.5 * b)).max(0.0);
+
+        let outputs = network.get_outputs();
+        assert!((outputs[0] - first).abs() < 1e-6);
+        assert!((outputs[1] - second).abs() < 1e-6);
+
+        let invalid = [
+            DUMP.replace("\"relu\"", "\"hat\""),
+            DUMP.replace("\"max\"", "\"median\""),
+            DUMP.replace("[14, 0]", "[15, 0]"),
+            DUMP.replace("[-2, 1]", "[-2, -1]"),
+            DUMP.replace("\"key\": 1,", "\"key\": 0,"),
+            DUMP.replace("\"num_outputs\": 2", "\"num_outputs\": 3"),
+        ];
+        for dump in invalid.iter() {
+            assert!(Network::from_neat_python(dump).is_err(), "{}", dump);
+        }
+    }
+}
@@ -2,8 +2,18 @@ use indexmap::IndexMap;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
-
-use super::genome::Genome;
+use std::time::Instant;
+
+use super::activation::{Activation, Aggregation};
+use super::codegen::{self, Source};
+use super::evaluator::Evaluation;
+use super::genome::{Genome, GenomeError, Neuron};
+use super::json::{ConnectionEntry, Document};
+use super::neat::NeatSettings;
+use super::neat_python;
+#[cfg(feature = "onnx")]
+use super::onnx;
+use super::visualize::{Graph, Link};
 
 /// Task that can be executed by `Network` and train `Neat`
 pub trait Task {
@@ -13,6 +23,11 @@ pub trait Task {
     ///
     /// * `seed` - Random seed to be used in Tasks that use random generators
     fn new(seed: u64) -> Self;
+    /// Inputs to `prop` through `Network` before the first `step`. Defaults to `None` which passes
+    /// outputs of a freshly reset `Network` (all 0.0) to the first `step`
+    fn initial_inputs(&self) -> Option<Vec<f32>> {
+        None
+    }
     /// Function to execute step of `Task` and returns outputs
     ///
     /// # Arguments
@@ -22,6 +37,49 @@ pub trait Task {
     /// Returns score or fitness of `Task`. Should return `None` if `Task` is still ongoing and
     /// should return `Some` with score when completed
     fn score(&self) -> Option<f32>;
+    /// Score of `Task` so far, used when an episode is stopped early by `NeatSettings`
+    /// `max_steps` or `timeout`. Defaults to `None` which uses `NeatSettings` `budget_fitness`
+    fn partial_score(&self) -> Option<f32> {
+        None
+    }
+    /// Behaviour characterisation of the episode used by novelty search, such as the final
+    /// position in a maze. Called once the episode has ended, behaviours should have the same
+    /// length for every episode. Defaults to `None` which gives every genome the same novelty
+    fn behavior(&self) -> Option<Vec<f32>> {
+        None
+    }
+    /// Objectives of the episode to be maximized together, such as reward and negative energy
+    /// use, used by `Selection::Pareto`. Called once the episode has ended and should always have
+    /// the same length. Defaults to `None` which makes every genome equal
+    fn objectives(&self) -> Option<Vec<f32>> {
+        None
+    }
+}
+
+/// Single run of a task driven by `Network`, implemented for every `Task`
+pub(crate) trait Episode {
+    fn initial_inputs(&mut self) -> Option<Vec<f32>>;
+    fn step(&mut self, inputs: Vec<f32>) -> Vec<f32>;
+    fn score(&self) -> Option<f32>;
+    fn partial_score(&mut self) -> Option<f32>;
+}
+
+impl<T: Task> Episode for T {
+    fn initial_inputs(&mut self) -> Option<Vec<f32>> {
+        Task::initial_inputs(self)
+    }
+
+    fn step(&mut self, inputs: Vec<f32>) -> Vec<f32> {
+        Task::step(self, inputs)
+    }
+
+    fn score(&self) -> Option<f32> {
+        Task::score(self)
+    }
+
+    fn partial_score(&mut self) -> Option<f32> {
+        Task::partial_score(self)
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -34,6 +92,9 @@ struct Edge {
 struct Node {
     value: f32,
     activation: f32,
+    bias: f32,
+    function: Activation,
+    aggregation: Aggregation,
     inputs: Vec<Edge>,
 }
 
@@ -45,12 +106,20 @@ pub struct Network {
     outputs: u16,
 }
 
-fn sigmoid(x: f32, activation: f32) -> f32 {
-    1.0 / (1.0 + (-activation * x).exp())
-}
-
 impl Network {
-    pub(crate) fn new(genome: Genome) -> Network {
+    /// Create network from the enabled connections of a genome, returns an error if
+    /// `Genome` `validate` finds a structural problem
+    ///
+    /// # Arguments
+    ///
+    /// * `genome` - Genome to build the network from
+    pub fn new(genome: Genome) -> Result<Network, GenomeError> {
+        genome.validate()?;
+        Ok(Network::from_genome(genome))
+    }
+
+    /// Create network from the enabled connections of a genome known to be valid
+    pub(crate) fn from_genome(genome: Genome) -> Network {
         let mut nodes: IndexMap<u16, Node> = genome
             .nodes
             .iter()
@@ -60,6 +129,9 @@ impl Network {
                     Node {
                         value: 0.0,
                         activation: n.activation,
+                        bias: n.bias,
+                        function: n.function,
+                        aggregation: n.aggregation,
                         inputs: Vec::new(),
                     },
                 )
@@ -90,6 +162,10 @@ impl Network {
         }
     }
 
+    pub(crate) fn inputs(&self) -> usize {
+        self.inputs as usize
+    }
+
     /// Retrieve outputs of `Network`, should match length of inputs for `Task`
     pub fn get_outputs(&self) -> Vec<f32> {
         self.nodes
@@ -100,6 +176,189 @@ impl Network {
             .collect()
     }
 
+    fn graph(&self) -> Graph {
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|(i, n)| (*i, n.activation, n.function, n.aggregation))
+            .collect();
+        let links = self
+            .nodes
+            .iter()
+            .flat_map(|(end, node)| {
+                node.inputs.iter().map(move |edge| Link {
+                    start: edge.start,
+                    end: *end,
+                    weight: edge.weight,
+                    enabled: true,
+                })
+            })
+            .collect();
+
+        Graph::new(self.inputs, self.outputs, nodes, links)
+    }
+
+    /// Graphviz DOT describing the network. Inputs and outputs are ranked on opposite sides,
+    /// connection colour and thickness show the sign and magnitude of weights and node labels show
+    /// their function, activation and aggregation when it isn't sum
+    pub fn to_dot(&self) -> String {
+        self.graph().to_dot(false)
+    }
+
+    /// SVG image of the network drawn in layers from inputs to outputs, does not require Graphviz
+    pub fn to_svg(&self) -> String {
+        self.graph().to_svg(false)
+    }
+
+    /// Rust source code reproducing `prop` exactly without depending on this crate. Feedforward
+    /// networks become a function `fn_name(inputs: [f32; I]) -> [f32; O]`. Recurrent networks,
+    /// whose outputs depend on previous values, become a struct named after `fn_name` in camel
+    /// case with `new`, `Default`, `reset` and `prop(&mut self, inputs: [f32; I]) -> [f32; O]`.
+    /// The code compiles without warnings, inputs no connection reads are left out
+    ///
+    /// # Arguments
+    ///
+    /// * `fn_name` - Name of the generated function
+    pub fn to_rust_source(&self, fn_name: &str) -> String {
+        codegen::rust_source(fn_name, self.inputs, self.outputs, &self.sources())
+    }
+
+    /// ONNX model computing the same outputs as `prop` for feedforward networks, with a float
+    /// input named `inputs` of shape `[1, inputs]` and output named `outputs` of shape
+    /// `[1, outputs]`. Returns `None` if the network is recurrent or has nodes that don't use
+    /// sigmoid and sum
+    #[cfg(feature = "onnx")]
+    pub fn to_onnx(&self) -> Option<Vec<u8>> {
+        onnx::export(self.inputs, self.outputs, &self.sources())
+    }
+
+    /// JSON describing the network in the format below. Node order and the order of connections
+    /// into each node are kept so `from_json` reproduces `prop` exactly
+    ///
+    /// ```json
+    /// {
+    ///   "version": 1,
+    ///   "inputs": 2,
+    ///   "outputs": 1,
+    ///   "nodes": [
+    ///     { "id": 0, "type": "input", "activation": 4.9, "bias": 0.0,
+    ///       "function": "sigmoid", "aggregation": "sum" },
+    ///     { "id": 1, "type": "input", "activation": 4.9, "bias": 0.0,
+    ///       "function": "sigmoid", "aggregation": "sum" },
+    ///     { "id": 2, "type": "output", "activation": 4.9, "bias": -0.5,
+    ///       "function": "tanh", "aggregation": "sum" },
+    ///     { "id": 5, "type": "hidden", "activation": 4.9, "bias": 0.0,
+    ///       "function": "sigmoid", "aggregation": "sum" }
+    ///   ],
+    ///   "connections": [
+    ///     { "in": 0, "out": 5, "weight": 1.0, "enabled": true },
+    ///     { "in": 5, "out": 2, "weight": 0.7, "enabled": true }
+    ///   ]
+    /// }
+    /// ```
+    ///
+    /// Input ids are `0..inputs` and output ids follow them, hidden nodes may use any other id.
+    /// Each node computes `function(activation * aggregation(inputs) + bias)` over its enabled
+    /// incoming connections multiplied by their weights. Functions are the lowercase names of
+    /// `Activation` and aggregations of `Aggregation`
+    pub fn to_json(&self) -> String {
+        let mut document = Document::new(self.inputs, self.outputs);
+        for (id, node) in self.nodes.iter() {
+            let neuron = Neuron {
+                activation: node.activation,
+                bias: node.bias,
+                function: node.function,
+                aggregation: node.aggregation,
+            };
+            document.push_node(*id, neuron);
+        }
+
+        for (end, node) in self.nodes.iter() {
+            for edge in node.inputs.iter() {
+                document.connections.push(ConnectionEntry {
+                    innovation: None,
+                    start: edge.start,
+                    end: *end,
+                    weight: edge.weight,
+                    enabled: true,
+                });
+            }
+        }
+
+        document.to_json()
+    }
+
+    /// Load a network from JSON written by `to_json` or `Neat` `best_json`, or written by hand.
+    /// `bias` defaults to 0.0, `function` to sigmoid, `aggregation` to sum, `enabled` to true and
+    /// `innovation` is ignored. Returns an error if
+    /// the JSON does not match the format or describes an invalid network, such as missing inputs
+    /// or outputs, duplicate ids or connections, connections into inputs or to unknown nodes
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - JSON in the format described in `to_json`
+    pub fn from_json(json: &str) -> serde_json::Result<Network> {
+        let document: Document = serde_json::from_str(json)?;
+        Ok(Network::from_genome(document.into_genome()?))
+    }
+
+    /// Load a genome trained with neat-python, dumped to JSON with its node and connection genes
+    /// by the function below
+    ///
+    /// ```python
+    /// import json
+    ///
+    /// def dump(genome, config, path):
+    ///     genome_config = config.genome_config
+    ///     data = {
+    ///         "num_inputs": genome_config.num_inputs,
+    ///         "num_outputs": genome_config.num_outputs,
+    ///         "nodes": [
+    ///             {"key": key, "bias": node.bias, "response": node.response,
+    ///              "activation": node.activation, "aggregation": node.aggregation}
+    ///             for key, node in genome.nodes.items()
+    ///         ],
+    ///         "connections": [
+    ///             {"key": list(key), "weight": conn.weight, "enabled": conn.enabled}
+    ///             for key, conn in genome.connections.items()
+    ///         ],
+    ///     }
+    ///     with open(path, "w") as f:
+    ///         json.dump(data, f)
+    /// ```
+    ///
+    /// Inputs `-1, -2, ..` become `0, 1, ..`, outputs `0, 1, ..` follow them and hidden nodes are
+    /// numbered after the outputs. neat-python scales the input of some activations, so
+    /// `activation` becomes `response` and `bias` becomes `bias` multiplied by that scale (5 for
+    /// sigmoid and sin, 2.5 for tanh and sqrt(5) for gauss). Supported activations are sigmoid,
+    /// tanh, sin, gauss, relu, identity, clamped, abs and square, supported aggregations are
+    /// sum, product, max, min, mean and maxabs. Feedforward genomes produce the same outputs as
+    /// neat-python `FeedForwardNetwork` apart from its clamping of extreme values and nodes
+    /// without inputs, which always aggregate to 0.0
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - Genome dumped by the function above
+    pub fn from_neat_python(json: &str) -> serde_json::Result<Network> {
+        Ok(Network::from_genome(neat_python::genome(json)?))
+    }
+
+    fn sources(&self) -> IndexMap<u16, Source> {
+        self.nodes
+            .iter()
+            .map(|(i, node)| {
+                let source = Source {
+                    activation: node.activation,
+                    bias: node.bias,
+                    function: node.function,
+                    aggregation: node.aggregation,
+                    inputs: node.inputs.iter().map(|e| (e.start, e.weight)).collect(),
+                };
+                (*i, source)
+            })
+            .collect()
+    }
+
     /// Reset all node values to 0.0
     pub fn reset(&mut self) {
         for (_, node) in self.nodes.iter_mut() {
@@ -108,10 +367,11 @@ impl Network {
     }
 
     fn eval(&mut self, node: Node, solved: &mut HashSet<u16>) -> f32 {
-        let mut val = 0.0;
+        let count = node.inputs.len();
+        let mut val = node.aggregation.start();
 
         for edge in node.inputs {
-            val += if solved.contains(&edge.start) {
+            let input = if solved.contains(&edge.start) {
                 self.nodes[&edge.start].value * edge.weight
             } else {
                 let n = self.nodes[&edge.start].clone();
@@ -121,10 +381,12 @@ impl Network {
 
                 self.nodes[&edge.start].value = v;
                 v * edge.weight
-            }
+            };
+            val = node.aggregation.fold(val, input);
         }
 
-        sigmoid(val, node.activation)
+        let val = node.aggregation.finish(val, count);
+        node.function.apply(node.activation * val + node.bias)
     }
 
     /// Propagate inputs throughout network
@@ -137,7 +399,7 @@ impl Network {
 
         let mut solved: HashSet<u16> = HashSet::new();
         for i in 0..self.inputs {
-            solved.insert(i as u16);
+            solved.insert(i);
         }
 
         for i in self.inputs..(self.inputs + self.outputs) {
@@ -149,12 +411,79 @@ impl Network {
     /// Run given `Task` to completion using network. This will take `Network` outputs and use them as inputs in `Task` `step`. Then run `prop` using `Task` outputs. Once `Task` `score` returns `Some`, execution will be stopped and the score from `Task` will be returned.
     pub fn run<T: Task>(&mut self) -> f32 {
         let mut rng = rand::thread_rng();
-        let mut task = T::new(rng.gen::<u64>());
+        self.run_seeded::<T>(rng.gen::<u64>())
+    }
+
+    /// Same as `run` but creates `Task` with the given seed. The network is reset before the
+    /// `Task` starts so every run begins from the same state
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - Seed passed to `Task` `new`
+    pub fn run_seeded<T: Task>(&mut self, seed: u64) -> f32 {
+        self.drive(&mut T::new(seed), None)
+    }
+
+    /// Run given `Task` once per seed and combine the scores using `NeatSettings` `aggregate`.
+    /// Episodes are stopped early if they exceed `max_steps` or `timeout`
+    ///
+    /// # Arguments
+    ///
+    /// * `seeds` - Seed for each episode
+    /// * `settings` - Settings containing aggregation and limits for each episode
+    pub fn run_episodes<T: Task>(&mut self, seeds: &[u64], settings: &NeatSettings) -> f32 {
+        let scores = seeds
+            .iter()
+            .map(|seed| self.run_limited::<T>(*seed, settings).fitness)
+            .collect();
+
+        settings.aggregate.apply(scores)
+    }
+
+    /// Runs an episode stopped early if it exceeds the limits of `settings`
+    pub(crate) fn run_limited<T: Task>(
+        &mut self,
+        seed: u64,
+        settings: &NeatSettings,
+    ) -> Evaluation {
+        let mut task = T::new(seed);
+        let fitness = self.drive(&mut task, Some(settings));
+
+        Evaluation {
+            fitness,
+            behavior: task.behavior().unwrap_or_default(),
+            objectives: task.objectives().unwrap_or_default(),
+            failure: None,
+        }
+    }
+
+    /// Resets the network and runs `task` until it has a score or exceeds the limits
+    pub(crate) fn drive<E: Episode>(&mut self, task: &mut E, limits: Option<&NeatSettings>) -> f32 {
+        self.reset();
+
+        if let Some(inputs) = task.initial_inputs() {
+            self.prop(inputs);
+        }
+
+        let start = Instant::now();
+        let mut steps = 0;
 
         while task.score().is_none() {
+            if let Some(settings) = limits {
+                let out_of_steps = settings.max_steps.is_some_and(|max| steps >= max);
+                let out_of_time = settings
+                    .timeout
+                    .is_some_and(|timeout| start.elapsed() >= timeout);
+
+                if out_of_steps || out_of_time {
+                    return task.partial_score().unwrap_or(settings.budget_fitness);
+                }
+            }
+
             let outputs = self.get_outputs();
             let inputs = task.step(outputs);
             self.prop(inputs);
+            steps += 1;
         }
 
         task.score().unwrap()
@@ -198,14 +527,70 @@ mod tests {
         }
     }
 
-    #[test]
-    fn test_network() {
+    struct Initial {
+        count: u8,
+    }
+
+    impl Task for Initial {
+        fn new(_: u64) -> Initial {
+            Initial { count: 0 }
+        }
+
+        fn initial_inputs(&self) -> Option<Vec<f32>> {
+            Some(vec![4.0])
+        }
+
+        fn step(&mut self, inputs: Vec<f32>) -> Vec<f32> {
+            assert_eq!(inputs.len(), 1);
+            assert_eq!(inputs[0], 0.5);
+            self.count += 1;
+
+            vec![4.0]
+        }
+
+        fn score(&self) -> Option<f32> {
+            if self.count < 2 {
+                None
+            } else {
+                Some(2.0)
+            }
+        }
+    }
+
+    struct Endless {
+        partial: bool,
+    }
+
+    impl Task for Endless {
+        fn new(seed: u64) -> Endless {
+            Endless { partial: seed == 1 }
+        }
+
+        fn step(&mut self, _: Vec<f32>) -> Vec<f32> {
+            vec![0.0]
+        }
+
+        fn score(&self) -> Option<f32> {
+            None
+        }
+
+        fn partial_score(&self) -> Option<f32> {
+            if self.partial {
+                Some(1.0)
+            } else {
+                None
+            }
+        }
+    }
+
+    fn test_genome() -> Genome {
         let mut connections = IndexMap::new();
         connections.insert(
             (0, 28),
             Connection {
                 weight: -3.0,
                 enabled: true,
+                innovation: None,
             },
         );
         connections.insert(
@@ -213,23 +598,60 @@ mod tests {
             Connection {
                 weight: -7.0,
                 enabled: true,
+                innovation: None,
             },
         );
 
         let mut nodes = IndexMap::new();
 
-        nodes.insert(0, Neuron { activation: 4.9 });
-        nodes.insert(1, Neuron { activation: 4.9 });
-        nodes.insert(28, Neuron { activation: 4.9 });
+        nodes.insert(0, Neuron::default());
+        nodes.insert(1, Neuron::default());
+        nodes.insert(28, Neuron::default());
 
-        let genome = Genome {
+        Genome {
             inputs: 1,
             outputs: 1,
             nodes,
             connections,
-        };
+        }
+    }
 
-        let mut network: Network = Network::new(genome);
+    #[test]
+    fn test_network() {
+        let mut network: Network = Network::new(test_genome()).unwrap();
+        assert_eq!(network.run::<Test>(), 3.0);
         assert_eq!(network.run::<Test>(), 3.0);
+
+        let mut genome = test_genome();
+        genome.nodes.shift_remove(&28);
+        assert_eq!(
+            Network::new(genome).err(),
+            Some(GenomeError::UnknownNode(28))
+        );
+    }
+
+    #[test]
+    fn test_initial_inputs() {
+        let mut network: Network = Network::new(test_genome()).unwrap();
+        assert_eq!(network.run::<Initial>(), 2.0);
+    }
+
+    #[test]
+    fn test_budget() {
+        let settings = NeatSettings {
+            max_steps: Some(10),
+            budget_fitness: -1.0,
+            ..NeatSettings::default()
+        };
+
+        let mut network: Network = Network::new(test_genome()).unwrap();
+        assert_eq!(network.run_episodes::<Endless>(&[0], &settings), -1.0);
+        assert_eq!(network.run_episodes::<Endless>(&[1], &settings), 1.0);
+
+        let settings = NeatSettings {
+            timeout: Some(std::time::Duration::from_millis(10)),
+            ..NeatSettings::default()
+        };
+        assert_eq!(network.run_episodes::<Endless>(&[1], &settings), 1.0);
     }
 }
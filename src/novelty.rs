@@ -0,0 +1,195 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use super::neat::Eviction;
+
+/// Behaviours added in one generation above which the threshold is raised
+const ADDED_MAX: usize = 4;
+/// Generations without additions after which the threshold is lowered
+const STAGNATION: usize = 5;
+
+/// Behaviours of genomes that were novel when they were evaluated, kept so search doesn't
+/// return to areas it has already visited
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct Archive {
+    /// Oldest first
+    behaviors: Vec<Vec<f32>>,
+    threshold: f32,
+    stagnation: usize,
+    capacity: Option<usize>,
+    eviction: Eviction,
+}
+
+fn distance(a: &[f32], b: &[f32]) -> f32 {
+    a.iter()
+        .zip(b.iter())
+        .map(|(x, y)| (x - y).powi(2))
+        .sum::<f32>()
+        .sqrt()
+}
+
+/// Mean distance from `behavior` to its `k` nearest neighbours in `others`
+fn sparseness<'a>(behavior: &[f32], others: impl Iterator<Item = &'a [f32]>, k: usize) -> f32 {
+    let mut distances: Vec<f32> = others.map(|other| distance(behavior, other)).collect();
+    if distances.is_empty() || k == 0 {
+        return 0.0;
+    }
+
+    distances.sort_unstable_by(|a, b| a.total_cmp(b));
+    distances.truncate(k);
+    distances.iter().sum::<f32>() / distances.len() as f32
+}
+
+impl Archive {
+    pub fn new(threshold: f32, capacity: Option<usize>, eviction: Eviction) -> Archive {
+        Archive {
+            behaviors: vec![],
+            threshold,
+            stagnation: 0,
+            capacity,
+            eviction,
+        }
+    }
+
+    pub fn behaviors(&self) -> &[Vec<f32>] {
+        &self.behaviors
+    }
+
+    /// Novelty of each behaviour compared to the rest of the population and the archive.
+    /// Behaviours of `candidates` more novel than the threshold are archived, evicting one when
+    /// the archive is over capacity. The threshold is raised by 20% when more than `ADDED_MAX`
+    /// are added and lowered by 5% after `STAGNATION` generations without additions. Empty
+    /// behaviours have no novelty
+    ///
+    /// # Arguments
+    ///
+    /// * `behaviors` - Behaviour of each genome in the population
+    /// * `candidates` - Whether each genome was evaluated since the last update, others were
+    ///   already considered for the archive
+    /// * `k` - Number of nearest neighbours averaged
+    /// * `rng` - Picks the behaviour evicted by `Eviction::Random`
+    pub fn update<R: Rng>(
+        &mut self,
+        behaviors: &[&[f32]],
+        candidates: &[bool],
+        k: usize,
+        rng: &mut R,
+    ) -> Vec<f32> {
+        let novelty: Vec<f32> = behaviors
+            .iter()
+            .enumerate()
+            .map(|(i, behavior)| {
+                if behavior.is_empty() {
+                    return 0.0;
+                }
+
+                let population = behaviors
+                    .iter()
+                    .enumerate()
+                    .filter(|(j, other)| *j != i && !other.is_empty())
+                    .map(|(_, other)| *other);
+                let archive = self.behaviors.iter().map(|other| &other[..]);
+                sparseness(behavior, population.chain(archive), k)
+            })
+            .collect();
+
+        let mut added = 0;
+        for ((behavior, novelty), candidate) in behaviors.iter().zip(novelty.iter()).zip(candidates)
+        {
+            if *candidate && *novelty > self.threshold {
+                self.behaviors.push(behavior.to_vec());
+                added += 1;
+            }
+        }
+
+        if let Some(capacity) = self.capacity {
+            while self.behaviors.len() > capacity {
+                match self.eviction {
+                    Eviction::Oldest => self.behaviors.remove(0),
+                    Eviction::Random => {
+                        let index = rng.gen_range(0, self.behaviors.len());
+                        self.behaviors.swap_remove(index)
+                    }
+                };
+            }
+        }
+
+        if added > ADDED_MAX {
+            self.threshold *= 1.2;
+        }
+
+        if added == 0 {
+            self.stagnation += 1;
+            if self.stagnation >= STAGNATION {
+                self.threshold *= 0.95;
+                self.stagnation = 0;
+            }
+        } else {
+            self.stagnation = 0;
+        }
+
+        novelty
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_archive() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut archive = Archive::new(1.5, None, Eviction::Oldest);
+        let behaviors: Vec<&[f32]> = vec![&[0.0, 0.0], &[1.0, 0.0], &[0.0, 1.0], &[4.0, 4.0], &[]];
+
+        let novelty = archive.update(&behaviors, &[true; 5], 2, &mut rng);
+        assert_eq!(novelty[0], 1.0);
+        assert_eq!(novelty[1], (1.0 + 2.0f32.sqrt()) / 2.0);
+        assert_eq!(novelty[4], 0.0);
+        assert_eq!(archive.behaviors(), &[vec![4.0, 4.0]]);
+
+        // Archived behaviours are no longer novel
+        assert_eq!(
+            archive.update(&[&[4.0, 4.0]], &[true], 1, &mut rng),
+            vec![0.0]
+        );
+
+        // Threshold is lowered until something is archived again
+        let pair: Vec<&[f32]> = vec![&[0.0, 0.0], &[1.0, 0.0]];
+        for _ in 0..STAGNATION * 7 {
+            archive.update(&pair, &[true, true], 1, &mut rng);
+        }
+        assert_eq!(archive.behaviors().len(), 1);
+
+        // Only candidates are archived, however novel the others are
+        let far: Vec<&[f32]> = vec![&[0.0, 0.0], &[1.0, 0.0], &[-9.0, -9.0]];
+        for _ in 0..STAGNATION {
+            archive.update(&far, &[true, true, false], 1, &mut rng);
+        }
+        assert_eq!(archive.behaviors().len(), 3);
+        assert!(!archive.behaviors().contains(&vec![-9.0, -9.0]));
+
+        // NaN distances don't stop the nearest neighbours being found
+        let nan: Vec<&[f32]> = vec![&[0.0, 0.0], &[f32::NAN, 0.0], &[0.0, 1.0]];
+        assert_eq!(sparseness(nan[0], nan[1..].iter().copied(), 1), 1.0);
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let behaviors: Vec<&[f32]> = vec![&[0.0], &[10.0], &[20.0]];
+        let later: Vec<&[f32]> = vec![&[30.0], &[40.0]];
+
+        let mut oldest = Archive::new(1.0, Some(3), Eviction::Oldest);
+        oldest.update(&behaviors, &[true; 3], 1, &mut rng);
+        oldest.update(&later, &[true; 2], 1, &mut rng);
+        assert_eq!(oldest.behaviors(), &[vec![20.0], vec![30.0], vec![40.0]]);
+
+        let mut random = Archive::new(1.0, Some(3), Eviction::Random);
+        random.update(&behaviors, &[true; 3], 1, &mut rng);
+        random.update(&later, &[true; 2], 1, &mut rng);
+        assert_eq!(random.behaviors().len(), 3);
+    }
+}
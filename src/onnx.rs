@@ -0,0 +1,464 @@
+use indexmap::IndexMap;
+use prost::Message;
+use std::collections::HashMap;
+
+use super::activation::{Activation, Aggregation};
+use super::codegen::Source;
+
+const FLOAT: i32 = 1;
+const INT64: i32 = 7;
+const ATTRIBUTE_INT: i32 = 2;
+const OPSET: i64 = 13;
+const IR_VERSION: i64 = 7;
+
+// Subset of onnx.proto needed to describe a network, field numbers match the ONNX spec
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct ModelProto {
+    #[prost(int64, tag = "1")]
+    pub(crate) ir_version: i64,
+    #[prost(string, tag = "2")]
+    pub(crate) producer_name: String,
+    #[prost(message, optional, tag = "7")]
+    pub(crate) graph: Option<GraphProto>,
+    #[prost(message, repeated, tag = "8")]
+    pub(crate) opset_import: Vec<OperatorSetIdProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct OperatorSetIdProto {
+    #[prost(string, tag = "1")]
+    pub(crate) domain: String,
+    #[prost(int64, tag = "2")]
+    pub(crate) version: i64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct GraphProto {
+    #[prost(message, repeated, tag = "1")]
+    pub(crate) node: Vec<NodeProto>,
+    #[prost(string, tag = "2")]
+    pub(crate) name: String,
+    #[prost(message, repeated, tag = "5")]
+    pub(crate) initializer: Vec<TensorProto>,
+    #[prost(message, repeated, tag = "11")]
+    pub(crate) input: Vec<ValueInfoProto>,
+    #[prost(message, repeated, tag = "12")]
+    pub(crate) output: Vec<ValueInfoProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct NodeProto {
+    #[prost(string, repeated, tag = "1")]
+    pub(crate) input: Vec<String>,
+    #[prost(string, repeated, tag = "2")]
+    pub(crate) output: Vec<String>,
+    #[prost(string, tag = "3")]
+    pub(crate) name: String,
+    #[prost(string, tag = "4")]
+    pub(crate) op_type: String,
+    #[prost(message, repeated, tag = "5")]
+    pub(crate) attribute: Vec<AttributeProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct AttributeProto {
+    #[prost(string, tag = "1")]
+    pub(crate) name: String,
+    #[prost(int64, tag = "3")]
+    pub(crate) i: i64,
+    #[prost(int32, tag = "20")]
+    pub(crate) r#type: i32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct TensorProto {
+    #[prost(int64, repeated, tag = "1")]
+    pub(crate) dims: Vec<i64>,
+    #[prost(int32, tag = "2")]
+    pub(crate) data_type: i32,
+    #[prost(float, repeated, tag = "4")]
+    pub(crate) float_data: Vec<f32>,
+    #[prost(int64, repeated, tag = "7")]
+    pub(crate) int64_data: Vec<i64>,
+    #[prost(string, tag = "8")]
+    pub(crate) name: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct ValueInfoProto {
+    #[prost(string, tag = "1")]
+    pub(crate) name: String,
+    #[prost(message, optional, tag = "2")]
+    pub(crate) r#type: Option<TypeProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct TypeProto {
+    #[prost(message, optional, tag = "1")]
+    pub(crate) tensor_type: Option<TensorTypeProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct TensorTypeProto {
+    #[prost(int32, tag = "1")]
+    pub(crate) elem_type: i32,
+    #[prost(message, optional, tag = "2")]
+    pub(crate) shape: Option<TensorShapeProto>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct TensorShapeProto {
+    #[prost(message, repeated, tag = "1")]
+    pub(crate) dim: Vec<Dimension>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct Dimension {
+    #[prost(int64, tag = "1")]
+    pub(crate) dim_value: i64,
+}
+
+fn value_info(name: &str, width: usize) -> ValueInfoProto {
+    let dim = vec![
+        Dimension { dim_value: 1 },
+        Dimension {
+            dim_value: width as i64,
+        },
+    ];
+
+    ValueInfoProto {
+        name: name.to_string(),
+        r#type: Some(TypeProto {
+            tensor_type: Some(TensorTypeProto {
+                elem_type: FLOAT,
+                shape: Some(TensorShapeProto { dim }),
+            }),
+        }),
+    }
+}
+
+fn node(op_type: &str, input: Vec<String>, output: &str, axis: Option<i64>) -> NodeProto {
+    NodeProto {
+        input,
+        output: vec![output.to_string()],
+        name: output.to_string(),
+        op_type: op_type.to_string(),
+        attribute: axis
+            .map(|i| AttributeProto {
+                name: String::from("axis"),
+                i,
+                r#type: ATTRIBUTE_INT,
+            })
+            .into_iter()
+            .collect(),
+    }
+}
+
+/// Layer of every node where each node only depends on nodes in earlier layers, `None` if the
+/// network has a cycle
+fn layers(inputs: u16, nodes: &IndexMap<u16, Source>) -> Option<HashMap<u16, usize>> {
+    let mut layer: HashMap<u16, usize> = (0..inputs).map(|i| (i, 0)).collect();
+    let mut remaining: Vec<u16> = nodes.keys().skip(inputs as usize).copied().collect();
+
+    while !remaining.is_empty() {
+        let before = remaining.len();
+        remaining.retain(|id| {
+            let deps: Option<Vec<usize>> = nodes[id]
+                .inputs
+                .iter()
+                .map(|(start, _)| layer.get(start).copied())
+                .collect();
+
+            match deps {
+                Some(deps) => {
+                    layer.insert(*id, deps.into_iter().max().unwrap_or(0) + 1);
+                    false
+                }
+                None => true,
+            }
+        });
+
+        if remaining.len() == before {
+            return None;
+        }
+    }
+
+    Some(layer)
+}
+
+/// Lowers a feedforward network to ONNX, every layer is a dense `MatMul` over all values
+/// computed so far followed by `Mul` with the node activations, `Add` with their biases and
+/// `Sigmoid`. Returns `None` for recurrent networks or ones with nodes using other activation
+/// functions or aggregations
+pub(crate) fn export(inputs: u16, outputs: u16, nodes: &IndexMap<u16, Source>) -> Option<Vec<u8>> {
+    let supported = |source: &Source| {
+        source.function == Activation::Sigmoid && source.aggregation == Aggregation::Sum
+    };
+    if !nodes.values().skip(inputs as usize).all(supported) {
+        return None;
+    }
+
+    let layer = layers(inputs, nodes)?;
+    let depth = layer.values().copied().max().unwrap_or(0);
+
+    let mut graph = GraphProto {
+        name: String::from("network"),
+        input: vec![value_info("inputs", inputs as usize)],
+        output: vec![value_info("outputs", outputs as usize)],
+        ..GraphProto::default()
+    };
+
+    // Column of each node in the state tensor
+    let mut column: HashMap<u16, usize> = (0..inputs).map(|i| (i, i as usize)).collect();
+    let mut state = String::from("inputs");
+
+    for l in 1..=depth {
+        let members: Vec<u16> = nodes.keys().filter(|id| layer[id] == l).copied().collect();
+        if members.is_empty() {
+            continue;
+        }
+
+        let width = column.len();
+        let mut weights = vec![0.0; width * members.len()];
+        for (j, id) in members.iter().enumerate() {
+            for (start, weight) in nodes[id].inputs.iter() {
+                weights[column[start] * members.len() + j] += weight;
+            }
+        }
+
+        let name = |kind: &str| format!("{}_{}", kind, l);
+        graph.initializer.push(TensorProto {
+            dims: vec![width as i64, members.len() as i64],
+            data_type: FLOAT,
+            float_data: weights,
+            name: name("weights"),
+            ..TensorProto::default()
+        });
+        graph.initializer.push(TensorProto {
+            dims: vec![members.len() as i64],
+            data_type: FLOAT,
+            float_data: members.iter().map(|id| nodes[id].activation).collect(),
+            name: name("activation"),
+            ..TensorProto::default()
+        });
+        graph.initializer.push(TensorProto {
+            dims: vec![members.len() as i64],
+            data_type: FLOAT,
+            float_data: members.iter().map(|id| nodes[id].bias).collect(),
+            name: name("bias"),
+            ..TensorProto::default()
+        });
+
+        graph.node.push(node(
+            "MatMul",
+            vec![state.clone(), name("weights")],
+            &name("sum"),
+            None,
+        ));
+        graph.node.push(node(
+            "Mul",
+            vec![name("sum"), name("activation")],
+            &name("scaled"),
+            None,
+        ));
+        graph.node.push(node(
+            "Add",
+            vec![name("scaled"), name("bias")],
+            &name("shifted"),
+            None,
+        ));
+        graph.node.push(node(
+            "Sigmoid",
+            vec![name("shifted")],
+            &name("values"),
+            None,
+        ));
+        graph.node.push(node(
+            "Concat",
+            vec![state, name("values")],
+            &name("state"),
+            Some(1),
+        ));
+
+        for id in members {
+            column.insert(id, column.len());
+        }
+        state = name("state");
+    }
+
+    graph.initializer.push(TensorProto {
+        dims: vec![outputs as i64],
+        data_type: INT64,
+        int64_data: (inputs..inputs + outputs)
+            .map(|i| column[&i] as i64)
+            .collect(),
+        name: String::from("output_columns"),
+        ..TensorProto::default()
+    });
+    graph.node.push(node(
+        "Gather",
+        vec![state, String::from("output_columns")],
+        "outputs",
+        Some(1),
+    ));
+
+    let model = ModelProto {
+        ir_version: IR_VERSION,
+        producer_name: String::from("neat"),
+        graph: Some(graph),
+        opset_import: vec![OperatorSetIdProto {
+            domain: String::new(),
+            version: OPSET,
+        }],
+    };
+
+    Some(model.encode_to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::neat::NeatSettings;
+    use crate::network::Network;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// Minimal ONNX interpreter for the operators used by `export`, tensors are kept as row major
+    /// data with their shape
+    fn run(model: &ModelProto, inputs: &[f32]) -> Vec<f32> {
+        let graph = model.graph.as_ref().unwrap();
+        let mut floats: HashMap<String, (Vec<usize>, Vec<f32>)> = HashMap::new();
+        let mut ints: HashMap<String, Vec<i64>> = HashMap::new();
+
+        for tensor in graph.initializer.iter() {
+            let dims = tensor.dims.iter().map(|d| *d as usize).collect();
+            match tensor.data_type {
+                FLOAT => {
+                    floats.insert(tensor.name.clone(), (dims, tensor.float_data.clone()));
+                }
+                INT64 => {
+                    ints.insert(tensor.name.clone(), tensor.int64_data.clone());
+                }
+                _ => panic!("Unsupported tensor type"),
+            }
+        }
+        floats.insert(
+            graph.input[0].name.clone(),
+            (vec![1, inputs.len()], inputs.to_vec()),
+        );
+
+        for node in graph.node.iter() {
+            let (a_shape, a) = floats[&node.input[0]].clone();
+            let result = match node.op_type.as_str() {
+                "MatMul" => {
+                    let (b_shape, b) = &floats[&node.input[1]];
+                    assert_eq!(a_shape[1], b_shape[0]);
+                    let values = (0..b_shape[1])
+                        .map(|j| (0..b_shape[0]).map(|k| a[k] * b[k * b_shape[1] + j]).sum())
+                        .collect();
+                    (vec![1, b_shape[1]], values)
+                }
+                "Mul" => {
+                    let (_, b) = &floats[&node.input[1]];
+                    (
+                        a_shape,
+                        a.iter().zip(b.iter()).map(|(x, y)| x * y).collect(),
+                    )
+                }
+                "Add" => {
+                    let (_, b) = &floats[&node.input[1]];
+                    (
+                        a_shape,
+                        a.iter().zip(b.iter()).map(|(x, y)| x + y).collect(),
+                    )
+                }
+                "Sigmoid" => (
+                    a_shape,
+                    a.iter().map(|x| 1.0 / (1.0 + (-x).exp())).collect(),
+                ),
+                "Concat" => {
+                    assert_eq!(node.attribute[0].i, 1);
+                    let (b_shape, b) = &floats[&node.input[1]];
+                    (vec![1, a_shape[1] + b_shape[1]], [a, b.clone()].concat())
+                }
+                "Gather" => {
+                    assert_eq!(node.attribute[0].i, 1);
+                    let indices = &ints[&node.input[1]];
+                    let values = indices.iter().map(|i| a[*i as usize]).collect();
+                    (vec![1, indices.len()], values)
+                }
+                op => panic!("Unsupported operator {}", op),
+            };
+            floats.insert(node.output[0].clone(), result);
+        }
+
+        floats[&graph.output[0].name].1.clone()
+    }
+
+    #[test]
+    fn test_onnx() {
+        let settings = NeatSettings {
+            add_connection_rate: 0.9,
+            add_node_rate: 0.5,
+            ..NeatSettings::default()
+        };
+        let mut rng = StdRng::seed_from_u64(4);
+        let mut exported = 0;
+
+        for mut genome in fixtures::genomes(4, 2, 10, 15, &settings, 4) {
+            for (_, node) in genome.nodes.iter_mut() {
+                node.bias = rng.gen_range(-1.0, 1.0);
+            }
+
+            let mut network = Network::new(genome).unwrap();
+            let bytes = match network.to_onnx() {
+                Some(bytes) => bytes,
+                None => continue,
+            };
+            let model = ModelProto::decode(bytes.as_slice()).unwrap();
+            exported += 1;
+
+            for _ in 0..5 {
+                let inputs: Vec<f32> = (0..4).map(|_| rng.gen_range(-2.0, 2.0)).collect();
+                network.reset();
+                network.prop(inputs.clone());
+
+                let expected = network.get_outputs();
+                let actual = run(&model, &inputs);
+                assert_eq!(expected.len(), actual.len());
+                for (e, a) in expected.iter().zip(actual.iter()) {
+                    assert!((e - a).abs() < 1e-5, "{} != {}", e, a);
+                }
+            }
+        }
+
+        assert!(exported > 0);
+    }
+
+    fn source(inputs: Vec<(u16, f32)>) -> Source {
+        Source {
+            activation: 4.9,
+            bias: 0.0,
+            function: Activation::Sigmoid,
+            aggregation: Aggregation::Sum,
+            inputs,
+        }
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let mut nodes = IndexMap::new();
+        nodes.insert(0, source(vec![]));
+        nodes.insert(1, source(vec![(2, 1.0)]));
+        nodes.insert(2, source(vec![(1, 1.0), (0, 1.0)]));
+        assert!(export(1, 1, &nodes).is_none());
+
+        nodes[&1].inputs.clear();
+        assert!(export(1, 1, &nodes).is_some());
+
+        nodes[&2].function = Activation::Tanh;
+        assert!(export(1, 1, &nodes).is_none());
+    }
+}
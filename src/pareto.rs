@@ -0,0 +1,141 @@
+/// Whether `a` is at least as good as `b` in every objective and better in one. Genomes with
+/// objectives dominate those without, such as genomes whose `Task` panicked
+fn dominates(a: &[f32], b: &[f32]) -> bool {
+    if a.is_empty() || b.is_empty() {
+        return !a.is_empty();
+    }
+
+    let mut better = false;
+    for (x, y) in a.iter().zip(b.iter()) {
+        if x < y {
+            return false;
+        }
+        better |= x > y;
+    }
+
+    better
+}
+
+/// Non-dominated sorting, returns the indices of `objectives` in each front starting with the
+/// Pareto front
+pub(crate) fn fronts(objectives: &[&[f32]]) -> Vec<Vec<usize>> {
+    let length = objectives.len();
+    let mut dominated: Vec<Vec<usize>> = vec![vec![]; length];
+    let mut counts = vec![0; length];
+
+    for i in 0..length {
+        for j in 0..length {
+            if dominates(objectives[i], objectives[j]) {
+                dominated[i].push(j);
+            } else if dominates(objectives[j], objectives[i]) {
+                counts[i] += 1;
+            }
+        }
+    }
+
+    let mut fronts = vec![];
+    let mut front: Vec<usize> = (0..length).filter(|i| counts[*i] == 0).collect();
+    while !front.is_empty() {
+        let mut next = vec![];
+        for i in front.iter() {
+            for j in dominated[*i].iter() {
+                counts[*j] -= 1;
+                if counts[*j] == 0 {
+                    next.push(*j);
+                }
+            }
+        }
+
+        fronts.push(front);
+        front = next;
+    }
+
+    fronts
+}
+
+/// Crowding distance of each member of `front`, the size of the cuboid around it formed by its
+/// neighbours in every objective. The extremes of each objective are infinitely far
+fn crowding(objectives: &[&[f32]], front: &[usize]) -> Vec<f32> {
+    let mut distance = vec![0.0; front.len()];
+    let count = front
+        .iter()
+        .map(|i| objectives[*i].len())
+        .min()
+        .unwrap_or(0);
+
+    let columns = (0..count).map(|j| front.iter().map(|i| objectives[*i][j]).collect::<Vec<_>>());
+    for values in columns {
+        let mut order: Vec<usize> = (0..front.len()).collect();
+        order.sort_unstable_by(|a, b| values[*a].total_cmp(&values[*b]));
+
+        let (first, last) = (order[0], order[order.len() - 1]);
+        distance[first] = f32::INFINITY;
+        distance[last] = f32::INFINITY;
+
+        // An infinite objective leaves only the extremes apart, the rest would be NaN
+        let range = values[last] - values[first];
+        if range <= 0.0 || !range.is_finite() {
+            continue;
+        }
+
+        for window in order.windows(3) {
+            distance[window[1]] += (values[window[2]] - values[window[0]]) / range;
+        }
+    }
+
+    distance
+}
+
+/// Rank of each genome for NSGA-II selection, higher is better. Genomes in earlier fronts rank
+/// above later ones and within a front less crowded genomes rank higher
+pub(crate) fn rank(objectives: &[&[f32]]) -> Vec<f32> {
+    let mut order = vec![];
+    for front in fronts(objectives) {
+        let distance = crowding(objectives, &front);
+        let mut members: Vec<(usize, f32)> = front.into_iter().zip(distance).collect();
+        members.sort_by(|a, b| b.1.total_cmp(&a.1));
+        order.extend(members.into_iter().map(|(i, _)| i));
+    }
+
+    let mut rank = vec![0.0; objectives.len()];
+    for (position, i) in order.into_iter().enumerate() {
+        rank[i] = (objectives.len() - position) as f32;
+    }
+
+    rank
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fronts() {
+        let objectives: Vec<&[f32]> = vec![
+            &[1.0, 5.0],
+            &[2.0, 4.0],
+            &[1.5, 4.0],
+            &[3.0, 1.0],
+            &[1.0, 1.0],
+            &[],
+        ];
+
+        assert_eq!(
+            fronts(&objectives),
+            vec![vec![0, 1, 3], vec![2], vec![4], vec![5]]
+        );
+        assert!(dominates(&[1.0, 1.0], &[1.0, 0.5]));
+        assert!(!dominates(&[1.0, 1.0], &[1.0, 1.0]));
+
+        // Extremes of the front are kept over its middle
+        let rank = rank(&objectives);
+        assert!(rank[0] > rank[1] && rank[3] > rank[1]);
+        assert!(rank[1] > rank[2] && rank[2] > rank[4] && rank[4] > rank[5]);
+        assert_eq!(crowding(&objectives, &[0, 1, 3])[1], 2.0);
+
+        // Infinite objectives don't make the crowding distance NaN
+        let infinite: Vec<&[f32]> = vec![&[f32::INFINITY, 0.0], &[1.0, 1.0], &[0.0, 2.0]];
+        assert_eq!(crowding(&infinite, &[0, 1, 2])[1], 1.0);
+        assert_eq!(super::rank(&infinite).len(), 3);
+    }
+}
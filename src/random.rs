@@ -0,0 +1,102 @@
+use rand::rngs::OsRng;
+use rand::{Error, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Random number generator that serializes its seed and position in the stream, so a saved run
+/// continues with the same random decisions it would have made without being saved
+#[derive(Clone, Debug)]
+pub(crate) struct Random {
+    seed: [u8; 32],
+    rng: ChaCha20Rng,
+}
+
+impl Random {
+    /// Seeded from `seed` or from entropy when it is `None`
+    pub(crate) fn new(seed: Option<u64>) -> Random {
+        let bytes = match seed {
+            Some(seed) => Seed::seed_from_u64(seed).0,
+            None => {
+                let mut bytes = [0; 32];
+                OsRng.fill_bytes(&mut bytes);
+                bytes
+            }
+        };
+
+        // Fills the first block so the position can be read before any numbers are generated
+        let mut rng = ChaCha20Rng::from_seed(bytes);
+        rng.set_word_pos(0);
+
+        Random { seed: bytes, rng }
+    }
+}
+
+/// Seed `SeedableRng` `seed_from_u64` expands a `u64` into, the same as `StdRng` uses
+struct Seed([u8; 32]);
+
+impl SeedableRng for Seed {
+    type Seed = [u8; 32];
+
+    fn from_seed(seed: [u8; 32]) -> Seed {
+        Seed(seed)
+    }
+}
+
+impl RngCore for Random {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
+
+impl Serialize for Random {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let position = self.rng.get_word_pos();
+        (self.seed, (position >> 64) as u64, position as u64).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Random {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Random, D::Error> {
+        let (seed, high, low) = <([u8; 32], u64, u64)>::deserialize(deserializer)?;
+        let mut rng = ChaCha20Rng::from_seed(seed);
+        rng.set_word_pos(((high as u128) << 64) | low as u128);
+
+        Ok(Random { seed, rng })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_random() {
+        let mut rng = Random::new(Some(4));
+        let mut std = rand::rngs::StdRng::seed_from_u64(4);
+        let mut same = Random::new(Some(4));
+        assert!((0..10).all(|_| same.gen::<u64>() == std.gen::<u64>()));
+
+        let fresh: Random = bincode::deserialize(&bincode::serialize(&rng).unwrap()).unwrap();
+        assert_eq!(fresh.rng.get_word_pos(), 0);
+        let _: Vec<u32> = (0..7).map(|_| rng.gen()).collect();
+
+        let bytes = bincode::serialize(&rng).unwrap();
+        let mut copy: Random = bincode::deserialize(&bytes).unwrap();
+        for _ in 0..20 {
+            assert_eq!(rng.gen::<u64>(), copy.gen::<u64>());
+        }
+    }
+}
@@ -0,0 +1,464 @@
+use serde::Deserialize;
+use serde_json::json;
+use std::io::{self, BufRead, BufReader, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use super::evaluator::{Evaluation, Evaluator};
+use super::neat::{panic_message, NeatSettings};
+use super::network::{Episode, Network, Task};
+
+#[derive(Deserialize)]
+struct Reply {
+    inputs: Option<Vec<f32>>,
+    score: Option<f32>,
+    behavior: Option<Vec<f32>>,
+    objectives: Option<Vec<f32>>,
+}
+
+struct Process {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<io::Result<String>>,
+}
+
+impl Process {
+    fn spawn(command: &(dyn Fn() -> Command + Send + Sync)) -> io::Result<Process> {
+        let mut child = command()
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+
+        let stdin = child.stdin.take().unwrap();
+        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+
+        // Replies are read on their own thread so waiting for one can time out
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || loop {
+            let mut line = String::new();
+            let result = stdout.read_line(&mut line).map(|_| line);
+            let end = !matches!(&result, Ok(line) if !line.is_empty());
+            if sender.send(result).is_err() || end {
+                break;
+            }
+        });
+
+        Ok(Process {
+            child,
+            stdin,
+            lines,
+        })
+    }
+
+    fn send(&mut self, message: serde_json::Value) -> io::Result<()> {
+        writeln!(self.stdin, "{}", message)?;
+        self.stdin.flush()
+    }
+
+    fn request(&mut self, message: serde_json::Value, timeout: Duration) -> io::Result<Reply> {
+        self.send(message)?;
+
+        let line = match self.lines.recv_timeout(timeout) {
+            Ok(line) => line?,
+            Err(RecvTimeoutError::Timeout) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "Process didn't reply in time",
+                ))
+            }
+            Err(RecvTimeoutError::Disconnected) => String::new(),
+        };
+        if line.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Process exited",
+            ));
+        }
+
+        Ok(serde_json::from_str(&line)?)
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+struct Session<'a> {
+    process: &'a mut Process,
+    timeout: Duration,
+    inputs: usize,
+    score: Option<f32>,
+    behavior: Option<Vec<f32>>,
+    objectives: Option<Vec<f32>>,
+}
+
+impl Episode for Session<'_> {
+    fn initial_inputs(&mut self) -> Option<Vec<f32>> {
+        None
+    }
+
+    fn step(&mut self, outputs: Vec<f32>) -> Vec<f32> {
+        let reply = self
+            .process
+            .request(json!({ "outputs": outputs }), self.timeout)
+            .unwrap_or_else(|err| panic!("Failed to communicate with process: {}", err));
+
+        match reply {
+            Reply {
+                inputs: Some(inputs),
+                ..
+            } => inputs,
+            Reply {
+                score: Some(score),
+                behavior,
+                objectives,
+                ..
+            } => {
+                self.score = Some(score);
+                self.behavior = behavior;
+                self.objectives = objectives;
+                vec![0.0; self.inputs]
+            }
+            _ => panic!("Process replied without inputs or score"),
+        }
+    }
+
+    fn score(&self) -> Option<f32> {
+        self.score
+    }
+
+    fn partial_score(&mut self) -> Option<f32> {
+        let reply = self
+            .process
+            .request(json!({ "stop": true }), self.timeout)
+            .unwrap_or_else(|err| panic!("Failed to communicate with process: {}", err));
+
+        self.behavior = reply.behavior;
+        self.objectives = reply.objectives;
+        reply.score
+    }
+}
+
+/// `Evaluator` that runs episodes in a pool of long lived external processes, so tasks can be
+/// written in other languages. Processes communicate with line delimited JSON over stdin and
+/// stdout:
+///
+/// * `{"seed": 42}` is sent when an episode starts
+/// * `{"outputs": [0.5]}` is sent with the network outputs, the process replies with
+///   `{"inputs": [1.0, 0.0]}` for the next step or `{"score": 3.0}` once the episode is finished
+/// * `{"stop": true}` is sent when an episode exceeds `NeatSettings` `max_steps` or `timeout`,
+///   the process replies with `{"score": 1.5}` or `{"score": null}` to use `budget_fitness`
+///
+/// Replies with a score can include the behaviour of the episode for novelty search and its
+/// objectives for multi-objective selection, as in
+/// `{"score": 3.0, "behavior": [0.2, 0.7], "objectives": [3.0, -1.2]}`
+///
+/// A process that exits, sends an invalid message or doesn't reply within the timeout is
+/// restarted and the genome it was running is given `NeatSettings` `panic_fitness`. Use with
+/// `Neat<External>`
+pub struct Subprocess {
+    command: Box<dyn Fn() -> Command + Send + Sync>,
+    processes: Vec<Option<Process>>,
+    timeout: Duration,
+}
+
+impl Subprocess {
+    /// Start a pool of processes
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - Number of processes, each runs one episode at a time. Must be above zero
+    /// * `command` - Creates the command used to start a process
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// use neat::{External, Neat, Subprocess};
+    /// use std::process::Command;
+    ///
+    /// let pool = Subprocess::new(8, || {
+    ///     let mut command = Command::new("python3");
+    ///     command.arg("simulator.py");
+    ///     command
+    /// })?;
+    ///
+    /// let mut neat = Neat::<External>::default(100, 4, 2);
+    /// neat.set_evaluator(Box::new(pool));
+    /// ```
+    pub fn new<F>(size: usize, command: F) -> io::Result<Subprocess>
+    where
+        F: Fn() -> Command + Send + Sync + 'static,
+    {
+        if size == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Subprocess needs at least one process",
+            ));
+        }
+
+        let processes = (0..size)
+            .map(|_| Process::spawn(&command).map(Some))
+            .collect::<io::Result<_>>()?;
+
+        Ok(Subprocess {
+            command: Box::new(command),
+            processes,
+            timeout: Duration::from_secs(60),
+        })
+    }
+
+    /// Sets how long to wait for each reply before the process is killed and restarted, 60
+    /// seconds by default
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Longest time a process should take to reply
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    fn run(
+        slot: &mut Option<Process>,
+        command: &(dyn Fn() -> Command + Send + Sync),
+        timeout: Duration,
+        mut network: Network,
+        seeds: &[u64],
+        settings: &NeatSettings,
+    ) -> Evaluation {
+        let mut episodes = vec![];
+
+        for seed in seeds {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| -> io::Result<Evaluation> {
+                let process = match slot {
+                    Some(process) => process,
+                    None => slot.insert(Process::spawn(command)?),
+                };
+                process.send(json!({ "seed": seed }))?;
+
+                let mut session = Session {
+                    process,
+                    timeout,
+                    inputs: network.inputs(),
+                    score: None,
+                    behavior: None,
+                    objectives: None,
+                };
+                let fitness = network.drive(&mut session, Some(settings));
+                Ok(Evaluation {
+                    fitness,
+                    behavior: session.behavior.unwrap_or_default(),
+                    objectives: session.objectives.unwrap_or_default(),
+                    failure: None,
+                })
+            }));
+
+            match result {
+                Ok(Ok(episode)) => episodes.push(episode),
+                Ok(Err(err)) => {
+                    *slot = None;
+                    return Evaluation::failed(settings, *seed, err.to_string());
+                }
+                Err(payload) => {
+                    *slot = None;
+                    return Evaluation::failed(settings, *seed, panic_message(&*payload));
+                }
+            }
+        }
+
+        Evaluation::combine(episodes, settings)
+    }
+}
+
+impl Evaluator for Subprocess {
+    fn evaluate(
+        &mut self,
+        networks: Vec<Network>,
+        seeds: &[u64],
+        settings: &NeatSettings,
+    ) -> Vec<f32> {
+        self.evaluations(networks, seeds, settings)
+            .into_iter()
+            .map(|evaluation| evaluation.fitness)
+            .collect()
+    }
+
+    fn evaluations(
+        &mut self,
+        networks: Vec<Network>,
+        seeds: &[u64],
+        settings: &NeatSettings,
+    ) -> Vec<Evaluation> {
+        let length = networks.len();
+        let queue = Mutex::new(networks.into_iter().enumerate().collect::<Vec<_>>());
+        let results = Mutex::new(vec![Evaluation::default(); length]);
+
+        let command = &*self.command;
+        let timeout = self.timeout;
+        let processes = &mut self.processes;
+        let (queue, shared) = (&queue, &results);
+        thread::scope(|scope| {
+            for slot in processes.iter_mut() {
+                scope.spawn(move || loop {
+                    let (id, network) = match queue.lock().unwrap().pop() {
+                        Some(job) => job,
+                        None => break,
+                    };
+
+                    let evaluation =
+                        Subprocess::run(slot, command, timeout, network, seeds, settings);
+                    shared.lock().unwrap()[id] = evaluation;
+                });
+            }
+        });
+
+        results.into_inner().unwrap()
+    }
+}
+
+/// Placeholder `Task` for a `Neat` whose genomes are all evaluated by an `Evaluator` such as
+/// `Subprocess`. Running it directly panics
+pub struct External;
+
+impl Task for External {
+    fn new(_: u64) -> External {
+        External
+    }
+
+    fn step(&mut self, _: Vec<f32>) -> Vec<f32> {
+        panic!("External tasks must be run by an Evaluator");
+    }
+
+    fn score(&self) -> Option<f32> {
+        None
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::neat::evaluate;
+
+    // Returns the step count as inputs and the third network output as score
+    const SCRIPT: &str = r#"
+        while read -r line; do
+            case "$line" in
+                *seed*) count=0 ;;
+                *stop*) echo '{"score": null}' ;;
+                *outputs*)
+                    count=$((count + 1))
+                    if [ "$count" -lt 3 ]; then
+                        echo "{\"inputs\": [$count]}"
+                    else
+                        value=${line#*[}
+                        echo "{\"score\": ${value%]*}, \"behavior\": [${value%]*}], \"objectives\": [$count]}"
+                    fi ;;
+            esac
+        done
+    "#;
+
+    struct Echo {
+        steps: u8,
+        value: f32,
+    }
+
+    impl Task for Echo {
+        fn new(_: u64) -> Echo {
+            Echo {
+                steps: 0,
+                value: 0.0,
+            }
+        }
+
+        fn step(&mut self, inputs: Vec<f32>) -> Vec<f32> {
+            self.steps += 1;
+            self.value = inputs[0];
+            vec![self.steps as f32]
+        }
+
+        fn score(&self) -> Option<f32> {
+            if self.steps < 3 {
+                None
+            } else {
+                Some(self.value)
+            }
+        }
+
+        fn behavior(&self) -> Option<Vec<f32>> {
+            Some(vec![self.value])
+        }
+
+        fn objectives(&self) -> Option<Vec<f32>> {
+            Some(vec![self.steps as f32])
+        }
+    }
+
+    fn networks() -> Vec<Network> {
+        fixtures::genomes(1, 1, 10, 1, &NeatSettings::default(), 5)
+            .into_iter()
+            .map(|genome| Network::new(genome).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_subprocess() {
+        let settings = NeatSettings {
+            episodes: 2,
+            ..NeatSettings::default()
+        };
+        let seeds = [1, 2];
+        let expected: Vec<Evaluation> = networks()
+            .into_iter()
+            .map(|network| evaluate::<Echo>(network, &seeds, &settings))
+            .collect();
+        let fitness: Vec<f32> = expected.iter().map(|e| e.fitness).collect();
+
+        let mut pool = Subprocess::new(3, || {
+            let mut command = Command::new("sh");
+            command.arg("-c").arg(SCRIPT);
+            command
+        })
+        .unwrap();
+
+        assert_eq!(pool.evaluate(networks(), &seeds, &settings), fitness);
+        assert_eq!(pool.evaluations(networks(), &seeds, &settings), expected);
+
+        let settings = NeatSettings {
+            max_steps: Some(1),
+            budget_fitness: -2.0,
+            ..settings
+        };
+        assert_eq!(pool.evaluate(networks(), &seeds, &settings), vec![-2.0; 10]);
+
+        assert!(Subprocess::new(0, || Command::new("true")).is_err());
+    }
+
+    #[test]
+    fn test_subprocess_timeout() {
+        let settings = NeatSettings {
+            panic_fitness: -5.0,
+            ..NeatSettings::default()
+        };
+
+        // Reads every message without replying
+        let mut pool = Subprocess::new(2, || {
+            let mut command = Command::new("sh");
+            command.arg("-c").arg("while read -r line; do :; done");
+            command
+        })
+        .unwrap();
+        pool.set_timeout(Duration::from_millis(100));
+
+        for evaluation in pool.evaluations(networks(), &[4], &settings) {
+            assert_eq!(evaluation.fitness, -5.0);
+            assert_eq!(evaluation.failure.unwrap().seed, 4);
+        }
+    }
+}
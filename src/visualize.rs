@@ -0,0 +1,430 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use super::activation::{Activation, Aggregation};
+
+const POSITIVE: &str = "#2166ac";
+const NEGATIVE: &str = "#b2182b";
+const DISABLED: &str = "#999999";
+
+const LAYER_SPACING: f32 = 140.0;
+const NODE_SPACING: f32 = 60.0;
+const RADIUS: f32 = 18.0;
+const MARGIN: f32 = 40.0;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Input,
+    Output,
+    Hidden,
+}
+
+pub(crate) struct Link {
+    pub(crate) start: u16,
+    pub(crate) end: u16,
+    pub(crate) weight: f32,
+    pub(crate) enabled: bool,
+}
+
+/// Nodes and connections of a `Network` or `Genome` to be rendered
+pub(crate) struct Graph {
+    /// Id, lines of the label below the id and kind of each node
+    nodes: Vec<(u16, Vec<String>, Kind)>,
+    links: Vec<Link>,
+}
+
+impl Graph {
+    /// Create graph from nodes in `Network` order (inputs, outputs then hidden) with their
+    /// activation, function and aggregation. Labels show the function and activation, and the
+    /// aggregation when it isn't sum
+    pub(crate) fn new(
+        inputs: u16,
+        outputs: u16,
+        nodes: Vec<(u16, f32, Activation, Aggregation)>,
+        links: Vec<Link>,
+    ) -> Graph {
+        let nodes = nodes
+            .into_iter()
+            .enumerate()
+            .map(|(i, (id, activation, function, aggregation))| {
+                let kind = match i as u16 {
+                    i if i < inputs => Kind::Input,
+                    i if i < inputs + outputs => Kind::Output,
+                    _ => Kind::Hidden,
+                };
+                let mut label = vec![format!("{:?} {:.2}", function, activation).to_lowercase()];
+                if aggregation != Aggregation::Sum {
+                    label.push(format!("{:?}", aggregation).to_lowercase());
+                }
+                (id, label, kind)
+            })
+            .collect();
+
+        Graph { nodes, links }
+    }
+
+    fn max_weight(&self) -> f32 {
+        self.links
+            .iter()
+            .map(|link| link.weight.abs())
+            .fold(0.0, f32::max)
+            .max(f32::EPSILON)
+    }
+
+    fn color(link: &Link) -> &'static str {
+        match link.enabled {
+            false => DISABLED,
+            true if link.weight < 0.0 => NEGATIVE,
+            true => POSITIVE,
+        }
+    }
+
+    pub(crate) fn to_dot(&self, show_disabled: bool) -> String {
+        let mut dot = String::from("digraph network {\n    rankdir=LR;\n");
+
+        for (kind, rank, shape) in [
+            (Kind::Input, "source", "box"),
+            (Kind::Output, "sink", "doublecircle"),
+        ] {
+            dot.push_str(&format!("    {{\n        rank={};\n", rank));
+            for (id, label, _) in self.nodes.iter().filter(|n| n.2 == kind) {
+                writeln!(
+                    dot,
+                    "        {} [label=\"{}\\n{}\" shape={}];",
+                    id,
+                    id,
+                    label.join("\\n"),
+                    shape
+                )
+                .unwrap();
+            }
+            dot.push_str("    }\n");
+        }
+
+        for (id, label, _) in self.nodes.iter().filter(|n| n.2 == Kind::Hidden) {
+            writeln!(
+                dot,
+                "    {} [label=\"{}\\n{}\" shape=circle];",
+                id,
+                id,
+                label.join("\\n")
+            )
+            .unwrap();
+        }
+
+        let max = self.max_weight();
+        for link in self
+            .links
+            .iter()
+            .filter(|link| link.enabled || show_disabled)
+        {
+            writeln!(
+                dot,
+                "    {} -> {} [color=\"{}\" penwidth={:.2} label=\"{:.2}\"{}];",
+                link.start,
+                link.end,
+                Graph::color(link),
+                0.5 + 3.5 * link.weight.abs() / max,
+                link.weight,
+                if link.enabled { "" } else { " style=dashed" }
+            )
+            .unwrap();
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Assigns each node a layer, inputs are first and outputs last with hidden nodes placed by
+    /// their longest path from an input ignoring connections that form cycles
+    fn layers(&self) -> Vec<Vec<u16>> {
+        let index: HashMap<u16, usize> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, n)| (n.0, i))
+            .collect();
+
+        let mut successors = vec![vec![]; self.nodes.len()];
+        for link in self.links.iter() {
+            successors[index[&link.start]].push(index[&link.end]);
+        }
+
+        // Depth first search to find which connections go backwards
+        let mut state = vec![0u8; self.nodes.len()];
+        let mut forward = vec![vec![]; self.nodes.len()];
+        for root in 0..self.nodes.len() {
+            if state[root] != 0 {
+                continue;
+            }
+
+            let mut stack = vec![(root, 0)];
+            state[root] = 1;
+            while let Some((node, next)) = stack.pop() {
+                if let Some(&succ) = successors[node].get(next) {
+                    stack.push((node, next + 1));
+                    match state[succ] {
+                        0 => {
+                            forward[node].push(succ);
+                            state[succ] = 1;
+                            stack.push((succ, 0));
+                        }
+                        1 => {}
+                        _ => forward[node].push(succ),
+                    }
+                } else {
+                    state[node] = 2;
+                }
+            }
+        }
+
+        let mut incoming = vec![0; self.nodes.len()];
+        for succs in forward.iter() {
+            for succ in succs {
+                incoming[*succ] += 1;
+            }
+        }
+
+        let mut layer = vec![0; self.nodes.len()];
+        let mut ready: Vec<usize> = (0..self.nodes.len())
+            .filter(|i| incoming[*i] == 0)
+            .collect();
+        while let Some(node) = ready.pop() {
+            if self.nodes[node].2 == Kind::Hidden {
+                layer[node] = layer[node].max(1);
+            }
+
+            for succ in forward[node].iter() {
+                layer[*succ] = layer[*succ].max(layer[node] + 1);
+                incoming[*succ] -= 1;
+                if incoming[*succ] == 0 {
+                    ready.push(*succ);
+                }
+            }
+        }
+
+        let last = self
+            .nodes
+            .iter()
+            .zip(layer.iter())
+            .filter(|(n, _)| n.2 == Kind::Hidden)
+            .map(|(_, l)| l + 1)
+            .max()
+            .unwrap_or(1);
+
+        let mut layers = vec![vec![]; last + 1];
+        for (i, (id, _, kind)) in self.nodes.iter().enumerate() {
+            let l = match kind {
+                Kind::Input => 0,
+                Kind::Output => last,
+                Kind::Hidden => layer[i],
+            };
+            layers[l].push(*id);
+        }
+
+        // Order nodes by the average position of the nodes connected into them
+        let mut position: HashMap<u16, f32> = HashMap::new();
+        for nodes in layers.iter_mut() {
+            let barycenter = |id: &u16| {
+                let (sum, count) = self
+                    .links
+                    .iter()
+                    .filter(|link| link.end == *id)
+                    .filter_map(|link| position.get(&link.start))
+                    .fold((0.0, 0), |(sum, count), p| (sum + p, count + 1));
+
+                if count == 0 {
+                    f32::MAX
+                } else {
+                    sum / count as f32
+                }
+            };
+
+            if nodes
+                .iter()
+                .all(|id| self.nodes[index[id]].2 == Kind::Hidden)
+            {
+                let mut keyed: Vec<(f32, u16)> =
+                    nodes.iter().map(|id| (barycenter(id), *id)).collect();
+                keyed.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+                *nodes = keyed.into_iter().map(|(_, id)| id).collect();
+            }
+
+            for (i, id) in nodes.iter().enumerate() {
+                position.insert(*id, i as f32);
+            }
+        }
+
+        layers
+    }
+
+    pub(crate) fn to_svg(&self, show_disabled: bool) -> String {
+        let layers = self.layers();
+        let tallest = layers.iter().map(|l| l.len()).max().unwrap_or(1) as f32;
+        let width = 2.0 * MARGIN + (layers.len() - 1) as f32 * LAYER_SPACING;
+        let height = 2.0 * MARGIN + (tallest - 1.0).max(0.0) * NODE_SPACING;
+
+        let mut coords: HashMap<u16, (f32, f32)> = HashMap::new();
+        for (l, nodes) in layers.iter().enumerate() {
+            let offset = (tallest - nodes.len() as f32) * NODE_SPACING / 2.0;
+            for (i, id) in nodes.iter().enumerate() {
+                let x = MARGIN + l as f32 * LAYER_SPACING;
+                let y = MARGIN + offset + i as f32 * NODE_SPACING;
+                coords.insert(*id, (x, y));
+            }
+        }
+
+        let mut svg = String::new();
+        writeln!(
+            svg,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{:.0}\" height=\"{:.0}\" \
+             viewBox=\"0 0 {:.0} {:.0}\" font-family=\"sans-serif\" font-size=\"10\">",
+            width, height, width, height
+        )
+        .unwrap();
+
+        let max = self.max_weight();
+        for link in self
+            .links
+            .iter()
+            .filter(|link| link.enabled || show_disabled)
+        {
+            let (x1, y1) = coords[&link.start];
+            let (x2, y2) = coords[&link.end];
+
+            // Connections that don't go right curve above the nodes so they stay visible
+            let path = if x2 > x1 {
+                format!("M {:.1} {:.1} L {:.1} {:.1}", x1, y1, x2, y2)
+            } else {
+                let (cx, cy) = ((x1 + x2) / 2.0, y1.min(y2) - NODE_SPACING);
+                format!(
+                    "M {:.1} {:.1} Q {:.1} {:.1} {:.1} {:.1}",
+                    x1, y1, cx, cy, x2, y2
+                )
+            };
+
+            writeln!(
+                svg,
+                "  <path d=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"{:.2}\"{}/>",
+                path,
+                Graph::color(link),
+                0.5 + 3.5 * link.weight.abs() / max,
+                if link.enabled {
+                    ""
+                } else {
+                    " stroke-dasharray=\"4 3\""
+                }
+            )
+            .unwrap();
+        }
+
+        for (id, label, kind) in self.nodes.iter() {
+            let (x, y) = coords[id];
+            let fill = match kind {
+                Kind::Input => "#d9f0d3",
+                Kind::Output => "#fddbc7",
+                Kind::Hidden => "#ffffff",
+            };
+
+            writeln!(
+                svg,
+                "  <circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"{:.1}\" fill=\"{}\" stroke=\"black\"/>",
+                x, y, RADIUS, fill
+            )
+            .unwrap();
+            writeln!(
+                svg,
+                "  <text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\">{}</text>",
+                x,
+                y - 1.0,
+                id
+            )
+            .unwrap();
+            for (line, text) in label.iter().enumerate() {
+                writeln!(
+                    svg,
+                    "  <text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\" font-size=\"8\">{}</text>",
+                    x,
+                    y + 9.0 + 8.0 * line as f32,
+                    text
+                )
+                .unwrap();
+            }
+        }
+
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph() -> Graph {
+        let links = vec![
+            Link {
+                start: 0,
+                end: 3,
+                weight: 2.0,
+                enabled: true,
+            },
+            Link {
+                start: 3,
+                end: 2,
+                weight: -1.0,
+                enabled: true,
+            },
+            Link {
+                start: 1,
+                end: 2,
+                weight: 0.5,
+                enabled: false,
+            },
+            Link {
+                start: 2,
+                end: 3,
+                weight: 0.1,
+                enabled: true,
+            },
+        ];
+
+        let node = |id, activation, function| (id, activation, function, Aggregation::Sum);
+        let nodes = vec![
+            node(0, 4.9, Activation::Sigmoid),
+            node(1, 4.9, Activation::Sigmoid),
+            node(2, 4.9, Activation::Tanh),
+            (3, 1.0, Activation::Gauss, Aggregation::Product),
+        ];
+
+        Graph::new(2, 1, nodes, links)
+    }
+
+    #[test]
+    fn test_dot() {
+        let dot = graph().to_dot(false);
+
+        assert!(dot.contains("0 [label=\"0\\nsigmoid 4.90\" shape=box];"));
+        assert!(dot.contains("2 [label=\"2\\ntanh 4.90\" shape=doublecircle];"));
+        assert!(dot.contains("3 [label=\"3\\ngauss 1.00\\nproduct\" shape=circle];"));
+        assert!(dot.contains("0 -> 3 [color=\"#2166ac\" penwidth=4.00 label=\"2.00\"];"));
+        assert!(dot.contains("3 -> 2 [color=\"#b2182b\" penwidth=2.25 label=\"-1.00\"];"));
+        assert!(!dot.contains("1 -> 2"));
+
+        assert!(graph()
+            .to_dot(true)
+            .contains("1 -> 2 [color=\"#999999\" penwidth=1.38 label=\"0.50\" style=dashed];"));
+    }
+
+    #[test]
+    fn test_layers() {
+        assert_eq!(graph().layers(), vec![vec![0, 1], vec![3], vec![2]]);
+
+        let svg = graph().to_svg(true);
+        assert_eq!(svg.matches("<circle").count(), 4);
+        assert_eq!(svg.matches("<path").count(), 4);
+        assert_eq!(svg.matches("stroke-dasharray").count(), 1);
+        assert!(svg.contains(">gauss 1.00</text>"));
+        assert!(svg.contains(">product</text>"));
+    }
+}